@@ -7,6 +7,16 @@ pub enum LoginMode {
     Qr,
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum)]
+pub enum EnvFormat {
+    Sh,
+    Fish,
+    Powershell,
+    Dotenv,
+    Json,
+    K8sSecret,
+}
+
 #[derive(Debug, Parser)]
 #[command(
     version,
@@ -21,11 +31,10 @@ pub struct Momento {
     #[arg(
         long,
         short,
-        default_value = "default",
         global = true,
-        help = "User profile"
+        help = "User profile. If not provided, the profile selected with `momento profile use` is used, or `default` if none is selected."
     )]
-    pub profile: String,
+    pub profile: Option<String>,
 
     #[command(subcommand)]
     pub command: Subcommand,
@@ -45,7 +54,7 @@ pub enum Subcommand {
             long = "endpoint",
             short = 'e',
             global = true,
-            help = "An explicit hostname to use; for example, cell-us-east-1-1.prod.a.momentohq.com"
+            help = "An explicit hostname to use; for example, cell-us-east-1-1.prod.a.momentohq.com. If not provided, your profile's endpoint is used."
         )]
         endpoint: Option<String>,
 
@@ -67,17 +76,113 @@ To delete a topic, stop subscribing to it."
             long = "endpoint",
             short = 'e',
             global = true,
-            help = "An explicit hostname to use; for example, cell-us-east-1-1.prod.a.momentohq.com"
+            help = "An explicit hostname to use; for example, cell-us-east-1-1.prod.a.momentohq.com. If not provided, your profile's endpoint is used."
         )]
         endpoint: Option<String>,
 
         #[command(subcommand)]
         operation: TopicCommand,
     },
-    #[command(about = "Configure credentials")]
+    #[command(
+        about = "Configure credentials",
+        group(
+            clap::ArgGroup::new("token-source")
+                .args(["token", "token_file", "token_env"]),
+        ),
+    )]
     Configure {
         #[arg(long, short)]
         quick: bool,
+        #[arg(
+            long = "token",
+            help = "Momento auth token to store in the profile. If no token option is provided, you are prompted for one."
+        )]
+        token: Option<String>,
+        #[arg(
+            long = "token-file",
+            help = "Read the auth token from this file",
+            value_name = "FILE"
+        )]
+        token_file: Option<String>,
+        #[arg(
+            long = "token-env",
+            help = "Read the auth token from this environment variable",
+            value_name = "ENV_VAR"
+        )]
+        token_env: Option<String>,
+        #[arg(
+            long = "cache",
+            help = "Default cache for the profile. If not provided, you are prompted for one unless --quick is set.",
+            value_name = "CACHE"
+        )]
+        cache: Option<String>,
+        #[arg(
+            long = "ttl",
+            help = "Default TTL, in seconds, for the profile. If not provided, you are prompted for one unless --quick is set."
+        )]
+        ttl: Option<u64>,
+        #[arg(
+            long = "endpoint",
+            short = 'e',
+            help = "An explicit hostname to store in the profile and use for its commands; for example, cell-us-east-1-1.prod.a.momentohq.com. If not provided, you are prompted for one unless --quick is set."
+        )]
+        endpoint: Option<String>,
+        #[arg(long = "no-create-cache", help = "Do not create the default cache")]
+        no_create_cache: bool,
+        #[arg(
+            long = "no-validate",
+            help = "Save the token without checking it with Momento, for example while offline"
+        )]
+        no_validate: bool,
+    },
+    #[command(about = "Manage profiles")]
+    Profile {
+        #[command(subcommand)]
+        operation: ProfileCommand,
+    },
+    #[command(about = "Diagnose problems with the CLI configuration and connectivity")]
+    Doctor {
+        #[arg(
+            long = "json",
+            help = "Print the checks as JSON, for example for a support ticket"
+        )]
+        json: bool,
+    },
+    #[command(
+        about = "Print the profile's token, endpoint and cache as environment variables for the Momento SDKs",
+        after_help = "Examples:\n  eval \"$(momento env)\"\n  momento env --format k8s-secret | kubectl apply -f -"
+    )]
+    Env {
+        #[arg(long, value_enum, default_value = "sh", help = "Output format")]
+        format: EnvFormat,
+        #[arg(
+            long = "secret-name",
+            default_value = "momento",
+            help = "Name of the secret when the format is k8s-secret"
+        )]
+        secret_name: String,
+    },
+    #[command(about = "Manage login sessions")]
+    Session {
+        #[command(subcommand)]
+        operation: SessionCommand,
+    },
+    #[command(about = "Remove the login session of the profile")]
+    Logout {},
+    #[command(about = "Work with API keys")]
+    Token {
+        #[command(subcommand)]
+        operation: TokenCommand,
+    },
+    #[command(about = "Encrypt the credentials file with a passphrase")]
+    Credentials {
+        #[command(subcommand)]
+        operation: CredentialsCommand,
+    },
+    #[command(about = "Manage the CLI configuration")]
+    Config {
+        #[command(subcommand)]
+        operation: ConfigCommand,
     },
     #[command(about = "Manage accounts", hide = true)]
     Account {
@@ -117,6 +222,154 @@ pub enum SigningKeyCommand {
     List {},
 }
 
+#[derive(Debug, Parser)]
+pub enum ProfileCommand {
+    #[command(about = "List the configured profiles, marking the current one")]
+    List {},
+
+    #[command(about = "Show the effective settings of a profile, with the token redacted")]
+    Show {
+        #[arg(help = "Profile to show. If not provided, the current profile is shown.")]
+        name: Option<String>,
+    },
+
+    #[command(about = "Delete a profile from the credentials and config files")]
+    Delete {
+        #[arg(help = "Profile to delete")]
+        name: String,
+        #[arg(
+            long,
+            help = "Delete the profile even if other profiles inherit from it. They stop inheriting from it."
+        )]
+        force: bool,
+    },
+
+    #[command(about = "Rename a profile in the credentials and config files")]
+    Rename {
+        #[arg(help = "Profile to rename")]
+        from: String,
+        #[arg(help = "New name for the profile")]
+        to: String,
+    },
+
+    #[command(about = "Copy a profile to a new name in the credentials and config files")]
+    Copy {
+        #[arg(help = "Profile to copy")]
+        from: String,
+        #[arg(help = "Name of the new profile")]
+        to: String,
+    },
+
+    #[command(about = "Use a profile when --profile is not passed")]
+    Use {
+        #[arg(help = "Profile to use")]
+        name: String,
+    },
+}
+
+#[derive(Debug, Parser)]
+pub enum SessionCommand {
+    #[command(about = "Show how long the login session of the profile remains valid")]
+    Status {},
+
+    #[command(about = "Log in again to replace the login session of the profile")]
+    Refresh {
+        #[arg(value_enum, default_value = "browser")]
+        via: LoginMode,
+    },
+}
+
+#[derive(Debug, Parser)]
+pub enum CredentialsCommand {
+    #[command(
+        about = "Encrypt the credentials file in place with a new passphrase",
+        long_about = "Encrypt the credentials file in place with a new passphrase. Only the credentials file is encrypted: tokens in config.toml, login sessions and tokens cached from credential_process with MOMENTO_CREDENTIAL_PROCESS_CACHE stay in plain text."
+    )]
+    Encrypt {},
+
+    #[command(about = "Decrypt the credentials file in place")]
+    Decrypt {},
+
+    #[command(
+        about = "Unlock the encrypted credentials file for later commands",
+        long_about = "Unlock the encrypted credentials file for later commands. The key of the file is kept in the state directory until the unlock expires or 'momento credentials lock' is run."
+    )]
+    Unlock {
+        #[arg(
+            long,
+            help = "How long to stay unlocked, defaults to $MOMENTO_CREDENTIALS_UNLOCK_SECONDS or 900"
+        )]
+        seconds: Option<u32>,
+    },
+
+    #[command(about = "Forget the unlocked passphrase of the credentials file")]
+    Lock {},
+}
+
+#[derive(Debug, Parser)]
+pub enum TokenCommand {
+    #[command(
+        about = "Print the endpoint, expiry and permissions of an API key, without calling Momento"
+    )]
+    Inspect {
+        #[arg(
+            help = "API key to inspect, or - to read it from stdin. If not provided, the key of the profile is inspected."
+        )]
+        token: Option<String>,
+    },
+
+    #[command(
+        about = "Replace the token of the profile with a newly generated one, keeping the old token as previous_token"
+    )]
+    Rotate {
+        #[arg(
+            long,
+            help = "How long until the new token expires, ex. 1d, 10m, 2M. If not provided, the new token is valid for as long as the old one was."
+        )]
+        valid_for: Option<String>,
+        #[arg(
+            long,
+            conflicts_with = "valid_for",
+            help = "Generate a token that never expires"
+        )]
+        never_expire: bool,
+    },
+}
+
+#[derive(Debug, Parser)]
+pub enum ConfigCommand {
+    #[command(about = "Convert the credentials and config files into a single config.toml")]
+    Migrate {
+        #[arg(long, help = "Overwrite an existing config.toml")]
+        force: bool,
+    },
+
+    #[command(
+        about = "Move the files in ~/.momento to the XDG config and state directories, $XDG_CONFIG_HOME/momento and $XDG_STATE_HOME/momento"
+    )]
+    MigrateDirs {},
+
+    #[command(about = "Print a setting of the profile")]
+    Get {
+        #[arg(help = "Setting to print: cache, ttl, endpoint, output or request_timeout_seconds")]
+        key: String,
+    },
+
+    #[command(about = "Change a setting of the profile")]
+    Set {
+        #[arg(help = "Setting to change: cache, ttl, endpoint, output or request_timeout_seconds")]
+        key: String,
+        #[arg(help = "New value of the setting")]
+        value: String,
+    },
+
+    #[command(about = "Remove an optional setting from the profile")]
+    Unset {
+        #[arg(help = "Setting to remove: endpoint, output or request_timeout_seconds")]
+        key: String,
+    },
+}
+
 const SIGNUP_DEPRECATED_MSG: &str =
     "*DECOMMISSIONED* Please go to the Momento Console (https://console.gomomento.com) to sign up.";
 
@@ -151,7 +404,7 @@ them to access the cache value without having access to a Momento auth token.
             long = "endpoint",
             short = 'e',
             global = true,
-            help = "An explicit hostname to use; for example, cell-us-east-1-1.prod.a.momentohq.com"
+            help = "An explicit hostname to use; for example, cell-us-east-1-1.prod.a.momentohq.com. If not provided, your profile's endpoint is used."
         )]
         endpoint: Option<String>,
 
@@ -190,6 +443,7 @@ tokens for accessing your Momento caches.
 This command will be used to generate api tokens to use with Momento. If `--never-expire` is specified,
 then the generated token will never expire. Else, it will expire after the specified number of seconds.
 Either `--valid-for` or `--never-expire` must be specified.
+
 "
     )]
     GenerateToken {
@@ -201,11 +455,17 @@ Either `--valid-for` or `--never-expire` must be specified.
         valid_for: Option<String>,
         #[arg(long, help = "Generate a token that never expires")]
         never_expire: bool,
+        #[arg(
+            long,
+            value_name = "PROFILE",
+            help = "Save the token, its refresh token and expiry to this profile instead of printing them"
+        )]
+        save_to_profile: Option<String>,
         #[arg(
             long = "endpoint",
             short = 'e',
             global = true,
-            help = "An explicit hostname to use; for example, cell-us-east-1-1.prod.a.momentohq.com"
+            help = "An explicit hostname to use; for example, cell-us-east-1-1.prod.a.momentohq.com. If not provided, your profile's endpoint is used."
         )]
         endpoint: Option<String>,
     },
@@ -486,5 +746,243 @@ pub enum TopicCommand {
 
         #[arg(help = "Name of the topic to which you would like to subscribe")]
         topic: String,
+
+        #[arg(
+            long = "filter-regex",
+            help = "Only keep messages whose payload matches this regular expression",
+            value_name = "REGEX"
+        )]
+        filter_regex: Option<String>,
+        #[arg(
+            long = "filter",
+            help = "Only keep JSON messages matching this predicate, for example '.type == \"order.failed\"'. Keys containing spaces or operators can be quoted, as in .[\"a=b\"]. May be repeated; all predicates must match.",
+            value_name = "PREDICATE"
+        )]
+        filters: Vec<String>,
+        #[arg(
+            long = "project",
+            help = "Only output these fields of JSON messages, for example '.type,.order.id'",
+            value_name = "FIELDS",
+            value_delimiter = ','
+        )]
+        projection: Vec<String>,
+
+        #[arg(
+            long = "exec",
+            help = "Command to run for each message. The payload is written to its stdin and message metadata is passed as MOMENTO_TOPIC_* environment variables.",
+            value_name = "COMMAND"
+        )]
+        exec: Option<String>,
+        #[arg(
+            long = "max-concurrency",
+            help = "Maximum number of --exec handlers to run at the same time",
+            default_value = "1",
+            value_parser = clap::value_parser!(u32).range(1..),
+            requires = "exec"
+        )]
+        max_concurrency: u32,
+        #[arg(
+            value_enum,
+            long = "on-error",
+            help = "What to do when an --exec handler fails",
+            default_value = "continue",
+            requires = "exec"
+        )]
+        on_error: ExecErrorMode,
+    },
+
+    /// Record the messages published to a topic into a newline-delimited JSON file.
+    #[command()]
+    Record {
+        #[arg(
+            long = "cache",
+            help = "Name of the cache you want to use as your topic namespace. If not provided, your profile's default cache is used.",
+            value_name = "CACHE"
+        )]
+        cache_name: Option<String>,
+
+        #[arg(help = "Name of the topic you would like to record")]
+        topic: String,
+        #[arg(
+            long = "out",
+            help = "File to write the recording to, one JSON message per line",
+            value_name = "FILE"
+        )]
+        out: String,
+    },
+
+    /// Republish the messages from a recording made with `topic record`.
+    #[command()]
+    Replay {
+        #[arg(
+            long = "cache",
+            help = "Name of the cache you want to use as your topic namespace. If not provided, your profile's default cache is used.",
+            value_name = "CACHE"
+        )]
+        cache_name: Option<String>,
+
+        #[arg(help = "Name of the topic to which you would like to republish the recording")]
+        topic: String,
+        #[arg(
+            long = "in",
+            help = "Recording file written by `topic record`",
+            value_name = "FILE"
+        )]
+        input: String,
+        #[arg(
+            long = "speed",
+            help = "Replay speed relative to the recording, for example 1x or 10x. Use `max` to publish as fast as possible.",
+            default_value = "1x",
+            value_parser = parse_replay_speed
+        )]
+        speed: ReplaySpeed,
     },
+
+    /// Measure publish-to-receive latency by publishing probe messages to a topic you are subscribed to.
+    #[command()]
+    Ping {
+        #[arg(
+            long = "cache",
+            help = "Name of the cache you want to use as your topic namespace. If not provided, your profile's default cache is used.",
+            value_name = "CACHE"
+        )]
+        cache_name: Option<String>,
+
+        #[arg(help = "Name of the probe topic", default_value = "momento-cli-ping")]
+        topic: String,
+        #[arg(
+            long = "rate",
+            help = "Probe messages to publish per second, at most 1000",
+            default_value = "1",
+            value_parser = parse_probe_rate
+        )]
+        rate: f64,
+        #[arg(
+            long = "count",
+            short = 'c',
+            help = "Stop after publishing this many probes. If not provided, probes are published until interrupted."
+        )]
+        count: Option<u64>,
+        #[arg(
+            long = "summary-interval",
+            help = "How often to print a summary while probing. ex. 10s, 1m",
+            default_value = "10s"
+        )]
+        summary_interval: String,
+        #[arg(
+            long = "timeout",
+            help = "How long to wait for outstanding probes after the last one is published. ex. 5s",
+            default_value = "5s"
+        )]
+        timeout: String,
+        #[arg(
+            long = "json",
+            help = "Print only a final JSON report instead of text summaries"
+        )]
+        json: bool,
+    },
+
+    /// Republish the messages of one or more topics from one profile's cache to another's.
+    #[command(
+        long_about = "Republish the messages of one or more topics from one profile's cache to another's.
+
+Payloads are republished unchanged, so consumers of the target cannot tell bridged messages apart.
+
+To bridge in both directions, pass `--bidirectional` rather than running two bridges. The bridge then
+remembers the payloads it republishes to each side for a minute, and skips them when they come back
+on its subscription to that side, so messages do not loop back and forth. Two separate bridges in
+opposite directions republish each other's messages forever."
+    )]
+    Bridge {
+        #[arg(long = "from-profile", help = "Profile to subscribe with")]
+        from_profile: String,
+        #[arg(
+            long = "from-cache",
+            help = "Cache to subscribe to. If not provided, the source profile's default cache is used.",
+            value_name = "CACHE"
+        )]
+        from_cache: Option<String>,
+        #[arg(long = "to-profile", help = "Profile to republish with")]
+        to_profile: String,
+        #[arg(
+            long = "to-cache",
+            help = "Cache to republish to. If not provided, the target profile's default cache is used.",
+            value_name = "CACHE"
+        )]
+        to_cache: Option<String>,
+        #[arg(
+            long = "topic",
+            required = true,
+            num_args = 1..,
+            help = "Topics to bridge"
+        )]
+        topics: Vec<String>,
+        #[arg(
+            long = "bidirectional",
+            help = "Also republish the messages of the target topics to the source, without looping them back"
+        )]
+        bidirectional: bool,
+        #[arg(
+            long = "from-endpoint",
+            help = "An explicit hostname for the source profile. If not provided, its profile's endpoint is used.",
+            value_name = "ENDPOINT"
+        )]
+        from_endpoint: Option<String>,
+        #[arg(
+            long = "to-endpoint",
+            help = "An explicit hostname for the target profile. If not provided, its profile's endpoint is used.",
+            value_name = "ENDPOINT"
+        )]
+        to_endpoint: Option<String>,
+        #[arg(
+            long = "stats-interval",
+            help = "How often to print throughput counters. ex. 10s, 1m",
+            default_value = "10s"
+        )]
+        stats_interval: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplaySpeed {
+    /// Keep the recorded gaps between messages, divided by this factor
+    Multiplier(f64),
+    /// Ignore the recorded gaps between messages
+    AsFastAsPossible,
+}
+
+fn parse_replay_speed(speed: &str) -> Result<ReplaySpeed, String> {
+    if speed.eq_ignore_ascii_case("max") {
+        return Ok(ReplaySpeed::AsFastAsPossible);
+    }
+    let multiplier = speed
+        .strip_suffix(['x', 'X'])
+        .unwrap_or(speed)
+        .parse::<f64>()
+        .map_err(|e| format!("expected a speed like 1x, 10x or max: {e}"))?;
+    // Tiny speeds make the delays between messages overflow.
+    if multiplier.is_finite() && multiplier >= 0.001 {
+        Ok(ReplaySpeed::Multiplier(multiplier))
+    } else {
+        Err("speed must be at least 0.001x".to_string())
+    }
+}
+
+fn parse_probe_rate(rate: &str) -> Result<f64, String> {
+    let rate = rate
+        .parse::<f64>()
+        .map_err(|e| format!("expected a number of probes per second: {e}"))?;
+    if rate > 0.0 && rate <= 1000.0 {
+        Ok(rate)
+    } else {
+        Err("rate must be greater than 0 and at most 1000".to_string())
+    }
+}
+
+#[derive(clap::ValueEnum, PartialEq, Eq, Debug, Clone, Copy)]
+pub enum ExecErrorMode {
+    /// Stop the subscription once in-flight handlers have finished
+    Stop,
+    /// Log the failure and keep handling messages
+    Continue,
 }
@@ -3,16 +3,21 @@ use std::process::exit;
 use std::time::Duration;
 
 use crate::{
+    config::OutputFormat,
     error::CliError,
     utils::{
-        client::{get_momento_client, interact_with_momento},
+        client::{
+            client_settings, get_momento_client, interact_with_momento,
+            print_whatever_this_is_as_json,
+        },
         console::console_data,
+        secret::SecretString,
     },
 };
 
 pub async fn create_cache(
     cache_name: String,
-    auth_token: String,
+    auth_token: SecretString,
     endpoint: Option<String>,
 ) -> Result<(), CliError> {
     let mut client = get_momento_client(auth_token, endpoint).await?;
@@ -22,7 +27,7 @@ pub async fn create_cache(
 
 pub async fn delete_cache(
     cache_name: String,
-    auth_token: String,
+    auth_token: SecretString,
     endpoint: Option<String>,
 ) -> Result<(), CliError> {
     let mut client = get_momento_client(auth_token, endpoint).await?;
@@ -30,22 +35,26 @@ pub async fn delete_cache(
     interact_with_momento("deleting cache...", client.delete_cache(&cache_name)).await
 }
 
-pub async fn list_caches(auth_token: String, endpoint: Option<String>) -> Result<(), CliError> {
+pub async fn list_caches(
+    auth_token: SecretString,
+    endpoint: Option<String>,
+) -> Result<(), CliError> {
     let mut client = get_momento_client(auth_token, endpoint).await?;
 
     let list_result = interact_with_momento("listing caches...", client.list_caches(None)).await?;
 
-    list_result
-        .caches
-        .into_iter()
-        .for_each(|cache| console_data!("{}", cache.cache_name));
+    let cache_names = list_result.caches.into_iter().map(|cache| cache.cache_name);
+    match client_settings().output {
+        OutputFormat::Text => cache_names.for_each(|cache_name| console_data!("{}", cache_name)),
+        OutputFormat::Json => print_whatever_this_is_as_json(&cache_names.collect::<Vec<_>>()),
+    }
 
     Ok(())
 }
 
 pub async fn flush_cache(
     cache_name: String,
-    auth_token: String,
+    auth_token: SecretString,
     endpoint: Option<String>,
 ) -> Result<(), CliError> {
     let mut client = get_momento_client(auth_token, endpoint).await?;
@@ -55,7 +64,7 @@ pub async fn flush_cache(
 
 pub async fn set(
     cache_name: String,
-    auth_token: String,
+    auth_token: SecretString,
     key: String,
     value: String,
     ttl_seconds: u64,
@@ -79,7 +88,7 @@ pub async fn set(
 
 pub async fn get(
     cache_name: String,
-    auth_token: String,
+    auth_token: SecretString,
     key: String,
     endpoint: Option<String>,
 ) -> Result<(), CliError> {
@@ -91,7 +100,10 @@ pub async fn get(
     match response {
         momento::response::Get::Hit { value } => {
             let value: String = value.try_into()?;
-            console_data!("{}", value);
+            match client_settings().output {
+                OutputFormat::Text => console_data!("{}", value),
+                OutputFormat::Json => print_whatever_this_is_as_json(&value),
+            }
         }
         momento::response::Get::Miss => {
             debug!("cache miss");
@@ -103,7 +115,7 @@ pub async fn get(
 
 pub async fn delete_key(
     cache_name: String,
-    auth_token: String,
+    auth_token: SecretString,
     key: String,
     endpoint: Option<String>,
 ) -> Result<(), CliError> {
@@ -0,0 +1,230 @@
+use crate::{
+    config::{
+        ensure_config_key, validate_config_value, ENV_VAR_NAME_MOMENTO_CONFIG_DIR,
+        REQUIRED_CONFIG_KEYS,
+    },
+    error::CliError,
+    utils::{
+        console::{console_data, console_info},
+        encryption::{parse_encrypted_file, read_protected_lines},
+        file::{
+            get_config_file_path, get_credentials_file_path, get_legacy_momento_dir,
+            get_toml_config_file_path, get_xdg_config_dir, get_xdg_state_dir,
+            lines_to_file_content, lock_config_dir, move_file, read_file_lines_if_exists,
+            write_to_file, CONFIG_DIR_FILE_NAMES, LOCK_FILE_NAME, STATE_DIR_FILE_NAMES,
+        },
+        inheritance::DEFAULTS_SECTION,
+        ini_config::{remove_profile_value, set_profile_value, IniDocument},
+        toml_config::{
+            migrate_ini_files, read_toml_config, write_toml_config, ProfileSettings, TomlConfig,
+        },
+        user::{get_inherited_profile_settings, get_profile_settings},
+    },
+};
+
+pub async fn migrate_config(force: bool) -> Result<(), CliError> {
+    let _lock = lock_config_dir().await?;
+    let toml_path = get_toml_config_file_path()?;
+    if toml_path.exists() && !force {
+        return Err(CliError {
+            msg: format!(
+                "{} already exists, pass --force to overwrite it",
+                toml_path.display()
+            ),
+        });
+    }
+
+    let config_lines = read_file_lines_if_exists(&get_config_file_path()?).await?;
+    let credentials_path = get_credentials_file_path()?;
+    let credentials_lines = read_file_lines_if_exists(&credentials_path).await?;
+    if parse_encrypted_file(&credentials_lines.join("\n")).is_some() {
+        return Err(CliError {
+            msg: format!(
+                "{} is encrypted and config.toml cannot be, run 'momento credentials decrypt' before migrating",
+                credentials_path.display()
+            ),
+        });
+    }
+    let migrated = migrate_ini_files(&config_lines, &credentials_lines)?;
+    if migrated.profiles.is_empty() {
+        return Err(CliError {
+            msg: "no profiles to migrate, please run 'momento configure' to configure your profile"
+                .to_string(),
+        });
+    }
+
+    write_toml_config(&migrated).await?;
+    console_info!(
+        "Migrated {} profile(s) to {}",
+        migrated.profiles.len(),
+        toml_path.display()
+    );
+    console_info!(
+        "The credentials and config files were left in place, but are no longer read for the migrated profiles"
+    );
+    Ok(())
+}
+
+/// Move the files in ~/.momento to the XDG directories, which are used once ~/.momento
+/// is gone.
+pub async fn migrate_config_dirs() -> Result<(), CliError> {
+    if std::env::var(ENV_VAR_NAME_MOMENTO_CONFIG_DIR).is_ok_and(|dir| !dir.is_empty()) {
+        return Err(CliError {
+            msg: format!(
+                "{ENV_VAR_NAME_MOMENTO_CONFIG_DIR} is set, so neither ~/.momento nor the XDG directories are used"
+            ),
+        });
+    }
+    let legacy_dir = get_legacy_momento_dir()?;
+    if !legacy_dir.is_dir() {
+        return Err(CliError {
+            msg: format!(
+                "{} does not exist, nothing to migrate",
+                legacy_dir.display()
+            ),
+        });
+    }
+    let config_dir = get_xdg_config_dir()?;
+    let state_dir = get_xdg_state_dir()?;
+    let moves: Vec<_> = CONFIG_DIR_FILE_NAMES
+        .iter()
+        .map(|name| (*name, &config_dir))
+        .chain(STATE_DIR_FILE_NAMES.iter().map(|name| (*name, &state_dir)))
+        .filter(|(name, _)| legacy_dir.join(name).exists())
+        .map(|(name, dir)| (legacy_dir.join(name), dir.join(name)))
+        .collect();
+    if let Some((_, to)) = moves.iter().find(|(_, to)| to.exists()) {
+        return Err(CliError {
+            msg: format!(
+                "{} already exists, move it out of the way to migrate {}",
+                to.display(),
+                legacy_dir.display()
+            ),
+        });
+    }
+
+    let lock = lock_config_dir().await?;
+    for dir in [&config_dir, &state_dir] {
+        tokio::fs::create_dir_all(dir).await.map_err(|e| CliError {
+            msg: format!("failed to create directory {}: {e}", dir.display()),
+        })?;
+    }
+    for (from, to) in &moves {
+        move_file(from, to).await?;
+        console_info!("Moved {} to {}", from.display(), to.display());
+    }
+    drop(lock);
+
+    let _ = tokio::fs::remove_file(legacy_dir.join(LOCK_FILE_NAME)).await;
+    if tokio::fs::remove_dir(&legacy_dir).await.is_err() {
+        console_info!(
+            "{} still holds other files and will be used until it is removed",
+            legacy_dir.display()
+        );
+    }
+    Ok(())
+}
+
+/// The section of config.toml to change for `profile`, added if the profile does not exist
+/// yet. Profiles that are still only in the INI files would not see changes made there.
+async fn toml_section_mut<'a>(
+    toml_config: &'a mut TomlConfig,
+    profile: &str,
+) -> Result<&'a mut ProfileSettings, CliError> {
+    if toml_config.section(profile).is_none() && is_ini_profile(profile).await? {
+        return Err(CliError {
+            msg: format!(
+                "profile {profile} is in the INI files but not in config.toml, run 'momento configure --profile {profile}' to add it to config.toml before changing it"
+            ),
+        });
+    }
+    Ok(if profile == DEFAULTS_SECTION {
+        toml_config.defaults.get_or_insert_with(Default::default)
+    } else {
+        toml_config.profiles.entry(profile.to_string()).or_default()
+    })
+}
+
+async fn is_ini_profile(profile: &str) -> Result<bool, CliError> {
+    Ok(
+        IniDocument::parse(&read_file_lines_if_exists(&get_config_file_path()?).await?)
+            .has_section(profile)
+            || IniDocument::parse(&read_protected_lines(&get_credentials_file_path()?).await?)
+                .has_section(profile),
+    )
+}
+
+async fn edit_ini_config_file(edit: impl FnOnce(&[String]) -> Vec<String>) -> Result<(), CliError> {
+    let path = get_config_file_path()?;
+    let contents = read_file_lines_if_exists(&path).await?;
+    write_to_file(&path, lines_to_file_content(edit(&contents))).await
+}
+
+/// Print the value `profile` uses for `key`, which may be inherited from another profile
+/// or the defaults section, in which case where it came from is reported too.
+pub async fn get_config_value(profile: &str, key: &str) -> Result<(), CliError> {
+    ensure_config_key(key)?;
+    match get_profile_settings(profile).await?.remove(key) {
+        Some(setting) => {
+            validate_config_value(key, &setting.value)?;
+            console_data!("{}", setting.value);
+            if setting.source != profile {
+                console_info!("(inherited from {})", setting.source);
+            }
+            Ok(())
+        }
+        None => Err(CliError {
+            msg: format!("{key} is not set for profile {profile}"),
+        }),
+    }
+}
+
+pub async fn set_config_value(profile: &str, key: &str, value: &str) -> Result<(), CliError> {
+    validate_config_value(key, value)?;
+    let lock = lock_config_dir().await?;
+    match read_toml_config().await? {
+        Some(mut toml_config) => {
+            toml_section_mut(&mut toml_config, profile)
+                .await?
+                .set_setting(key, value)?;
+            write_toml_config(&toml_config).await?;
+        }
+        None => {
+            edit_ini_config_file(|contents| set_profile_value(profile, key, value, contents))
+                .await?
+        }
+    }
+    drop(lock);
+    console_info!("Set {key} to {value} for profile {profile}");
+    Ok(())
+}
+
+pub async fn unset_config_value(profile: &str, key: &str) -> Result<(), CliError> {
+    ensure_config_key(key)?;
+    // A required setting can be unset when the profile inherits it from another profile
+    // or the defaults.
+    if REQUIRED_CONFIG_KEYS.contains(&key)
+        && !get_inherited_profile_settings(profile, key)
+            .await?
+            .contains_key(key)
+    {
+        return Err(CliError {
+            msg: format!("{key} is required and profile {profile} does not inherit it, use 'momento config set {key} <value>' to change it"),
+        });
+    }
+    let lock = lock_config_dir().await?;
+    match read_toml_config().await? {
+        Some(mut toml_config) => {
+            toml_section_mut(&mut toml_config, profile)
+                .await?
+                .unset_setting(key)?;
+            write_toml_config(&toml_config).await?;
+        }
+        None => {
+            edit_ini_config_file(|contents| remove_profile_value(profile, key, contents)).await?
+        }
+    }
+    drop(lock);
+    console_info!("Unset {key} for profile {profile}");
+    Ok(())
+}
@@ -1,28 +1,94 @@
+use chrono::Utc;
 use std::path::Path;
 use tokio::fs;
 
 use crate::config::DEFAULT_CACHE_NAME;
 use crate::utils::ini_config::{update_config_profile, update_credentials_profile};
+use crate::utils::toml_config::{read_toml_config, write_toml_config};
 use crate::{
     commands::cache::cache_cli::create_cache,
     config::{Config, Credentials},
     error::CliError,
     utils::{
+        api_key::decode_api_key,
+        client::{get_momento_client, interact_with_momento},
         console::console_info,
+        encryption::{read_protected_lines, write_protected_lines},
         file::{
             create_file, get_config_file_path, get_credentials_file_path, get_momento_config_dir,
-            open_file, prompt_user_for_input, read_file_contents, write_to_file,
+            lines_to_file_content, lock_config_dir, open_file, prompt_user_for_input,
+            read_file_contents, write_to_file,
         },
         ini_config::{
             create_new_config_profile, create_new_credentials_profile, does_profile_name_exist,
         },
+        secret::SecretString,
         user::{get_config_for_profile, get_creds_for_profile},
     },
 };
 
-pub async fn configure_momento(quick: bool, profile_name: &str) -> Result<(), CliError> {
-    let credentials = prompt_user_for_creds(profile_name).await?;
-    let config = prompt_user_for_config(quick, profile_name).await?;
+/// Values supplied as `configure` flags. Anything left as `None` is prompted for.
+#[derive(Default)]
+pub struct ConfigureOverrides {
+    pub token: Option<String>,
+    pub cache: Option<String>,
+    pub ttl: Option<u64>,
+    pub endpoint: Option<String>,
+    pub skip_cache_creation: bool,
+    pub skip_token_validation: bool,
+}
+
+/// Resolve the token from whichever of `--token`, `--token-file` or `--token-env` was given,
+/// looking up the variable of `--token-env` with `env`.
+pub async fn resolve_token_flags(
+    token: Option<String>,
+    token_file: Option<String>,
+    token_env: Option<String>,
+    env: impl Fn(&str) -> Result<String, std::env::VarError>,
+) -> Result<Option<String>, CliError> {
+    let token = if let Some(path) = token_file {
+        let contents = fs::read_to_string(&path).await.map_err(|e| CliError {
+            msg: format!("failed to read token file {path}: {e}"),
+        })?;
+        Some(contents)
+    } else if let Some(env_var) = token_env {
+        let value = env(&env_var).map_err(|e| CliError {
+            msg: format!("failed to read token from environment variable {env_var}: {e}"),
+        })?;
+        Some(value)
+    } else {
+        token
+    };
+    match token.map(|t| t.trim().to_string()) {
+        Some(t) if t.is_empty() => Err(CliError {
+            msg: "the provided token is empty".to_string(),
+        }),
+        token => Ok(token),
+    }
+}
+
+pub async fn configure_momento(
+    quick: bool,
+    profile_name: &str,
+    overrides: ConfigureOverrides,
+) -> Result<(), CliError> {
+    let (credentials, existing_caches) = prompt_user_for_creds(
+        profile_name,
+        overrides.token,
+        overrides.endpoint.clone(),
+        overrides.skip_token_validation,
+    )
+    .await?;
+    warn_if_token_expires_soon(credentials.token.expose_secret());
+    let config = prompt_user_for_config(
+        quick,
+        profile_name,
+        overrides.cache,
+        overrides.ttl,
+        overrides.endpoint,
+        existing_caches.as_deref(),
+    )
+    .await?;
 
     let momento_dir = get_momento_config_dir()?;
     let credentials_file_path = get_credentials_file_path()?;
@@ -36,25 +102,50 @@ pub async fn configure_momento(quick: bool, profile_name: &str) -> Result<(), Cl
             })
         }
     };
-    let creds_file_contents = ensure_file_exists_and_get_contents(&credentials_file_path).await?;
-    let new_creds_file_contents =
-        add_or_update_credentials_profile(profile_name, credentials.clone(), creds_file_contents)?;
-    write_to_file(
-        &credentials_file_path,
-        lines_to_file_content(new_creds_file_contents),
-    )
-    .await?;
-    let config_file_contents = ensure_file_exists_and_get_contents(&config_file_path).await?;
-    let new_config_file_contents =
-        add_or_update_profile_config(profile_name, config.clone(), config_file_contents)?;
-    write_to_file(
-        &config_file_path,
-        lines_to_file_content(new_config_file_contents),
-    )
-    .await?;
+    let lock = lock_config_dir().await?;
+    match read_toml_config().await? {
+        // Once the INI files have been migrated, profiles live in config.toml.
+        Some(mut toml_config) => {
+            let settings = toml_config
+                .profiles
+                .entry(profile_name.to_string())
+                .or_default();
+            settings.token = Some(credentials.token.clone());
+            settings.update_config(config.clone());
+            write_toml_config(&toml_config).await?;
+        }
+        None => {
+            let creds_file_contents = read_protected_lines(&credentials_file_path).await?;
+            let new_creds_file_contents = add_or_update_credentials_profile(
+                profile_name,
+                credentials.clone(),
+                creds_file_contents,
+            )?;
+            write_protected_lines(&credentials_file_path, new_creds_file_contents).await?;
+            let config_file_contents =
+                ensure_file_exists_and_get_contents(&config_file_path).await?;
+            let new_config_file_contents =
+                add_or_update_profile_config(profile_name, config.clone(), config_file_contents)?;
+            write_to_file(
+                &config_file_path,
+                lines_to_file_content(new_config_file_contents),
+            )
+            .await?;
+        }
+    }
 
-    // TODO: Update the endpoint to read from config
-    match create_cache(config.cache.clone(), credentials.token, None).await {
+    drop(lock);
+
+    if overrides.skip_cache_creation {
+        return Ok(());
+    }
+    match create_cache(
+        config.cache.clone(),
+        credentials.token,
+        config.endpoint.clone(),
+    )
+    .await
+    {
         Ok(_) => console_info!(
             "{} successfully created as the default with default TTL of {}s",
             config.cache.clone(),
@@ -72,10 +163,60 @@ pub async fn configure_momento(quick: bool, profile_name: &str) -> Result<(), Cl
     Ok(())
 }
 
-async fn prompt_user_for_creds(profile_name: &str) -> Result<Credentials, CliError> {
+/// How many times to prompt for a token that fails validation.
+const TOKEN_ATTEMPTS: u32 = 3;
+
+fn warn_if_token_expires_soon(token: &str) {
+    match decode_api_key(token) {
+        Ok(info) => {
+            if let Some(warning) = info.expiry_warning(Utc::now()) {
+                console_info!("Warning: {warning}");
+            }
+        }
+        // The token is still saved; Momento has the final say on whether it is valid.
+        Err(e) => log::debug!("could not decode the token: {}", e.msg),
+    }
+}
+
+/// Check that `token` is a Momento API key that Momento accepts, returning the names of
+/// the caches it can see.
+async fn validate_token(token: &str, endpoint: Option<String>) -> Result<Vec<String>, CliError> {
+    decode_api_key(token)?;
+    let mut client = get_momento_client(SecretString::new(token), endpoint).await?;
+    let caches = interact_with_momento("listing caches...", client.list_caches(None)).await?;
+    Ok(caches
+        .caches
+        .into_iter()
+        .map(|cache| cache.cache_name)
+        .collect())
+}
+
+/// Prompt for the token until it validates, unless it was passed as a flag or validation
+/// is skipped. Also returns the existing caches when the token was validated.
+async fn prompt_user_for_creds(
+    profile_name: &str,
+    token: Option<String>,
+    endpoint: Option<String>,
+    skip_validation: bool,
+) -> Result<(Credentials, Option<Vec<String>>), CliError> {
+    if let Some(token) = token {
+        return Ok((
+            Credentials {
+                token: token.into(),
+            },
+            None,
+        ));
+    }
     let current_credentials = get_creds_for_profile(profile_name)
         .await
         .unwrap_or_default();
+    let endpoint = match endpoint {
+        Some(endpoint) => Some(endpoint),
+        None => get_config_for_profile(profile_name)
+            .await
+            .ok()
+            .and_then(|config| config.endpoint),
+    };
 
     console_info!("Please paste your Momento auth token.  (If you do not have an auth token, use `momento account` to generate one.)");
     console_info!(
@@ -83,12 +224,81 @@ async fn prompt_user_for_creds(profile_name: &str) -> Result<Credentials, CliErr
     );
     console_info!("");
 
-    let token = prompt_user_for_input("Token", current_credentials.token.as_str(), true).await?;
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        let token =
+            prompt_user_for_input("Token", current_credentials.token.expose_secret(), true).await?;
+        if skip_validation {
+            return Ok((
+                Credentials {
+                    token: token.into(),
+                },
+                None,
+            ));
+        }
+        match validate_token(&token, endpoint.clone()).await {
+            Ok(caches) => {
+                return Ok((
+                    Credentials {
+                        token: token.into(),
+                    },
+                    Some(caches),
+                ))
+            }
+            Err(e) if attempts < TOKEN_ATTEMPTS => {
+                console_info!("That token did not work: {}", e.msg);
+                console_info!("Please try again.");
+            }
+            Err(e) => {
+                return Err(CliError {
+                    msg: format!(
+                        "{}. To save a token that cannot be checked right now, run 'momento configure --no-validate'",
+                        e.msg
+                    ),
+                })
+            }
+        }
+    }
+}
 
-    Ok(Credentials { token })
+/// The cache chosen from a numbered list of `existing_caches`, or the name of a new cache.
+/// Only a number from 1 to the length of the list picks a cache, so that new caches can have
+/// numeric names like 2024.
+fn pick_cache(input: &str, existing_caches: &[String]) -> String {
+    match input.parse::<usize>() {
+        Ok(number) if (1..=existing_caches.len()).contains(&number) => {
+            existing_caches[number - 1].clone()
+        }
+        _ => input.to_string(),
+    }
 }
 
-async fn prompt_user_for_config(quick: bool, profile_name: &str) -> Result<Config, CliError> {
+async fn prompt_user_to_pick_cache(
+    existing_caches: &[String],
+    default_cache: &str,
+) -> Result<String, CliError> {
+    console_info!("Existing caches:");
+    for (i, cache) in existing_caches.iter().enumerate() {
+        console_info!("  {}) {cache}", i + 1);
+    }
+    let input = prompt_user_for_input(
+        "Default Cache (a number from the list, or a new name to create it)",
+        default_cache,
+        false,
+    )
+    .await?;
+    Ok(pick_cache(&input, existing_caches))
+}
+
+async fn prompt_user_for_config(
+    quick: bool,
+    profile_name: &str,
+    cache: Option<String>,
+    ttl: Option<u64>,
+    endpoint: Option<String>,
+    existing_caches: Option<&[String]>,
+) -> Result<Config, CliError> {
     let current_config = get_config_for_profile(profile_name)
         .await
         .unwrap_or_default();
@@ -98,11 +308,13 @@ async fn prompt_user_for_config(quick: bool, profile_name: &str) -> Result<Confi
     } else {
         current_config.cache.as_str()
     };
-    let mut cache_name = prompt_cache.to_string();
-    if !quick {
-        cache_name = match prompt_user_for_input("Default Cache", prompt_cache, false).await {
-            Ok(s) => s,
-            Err(e) => return Err(e),
+    let mut cache_name = cache.clone().unwrap_or(prompt_cache.to_string());
+    if !quick && cache.is_none() {
+        cache_name = match existing_caches {
+            Some(caches) if !caches.is_empty() => {
+                prompt_user_to_pick_cache(caches, prompt_cache).await?
+            }
+            _ => prompt_user_for_input("Default Cache", prompt_cache, false).await?,
         };
     }
     let cache_name_to_use = if cache_name.is_empty() {
@@ -110,13 +322,14 @@ async fn prompt_user_for_config(quick: bool, profile_name: &str) -> Result<Confi
     } else {
         cache_name
     };
+    let ttl_flag_missing = ttl.is_none();
     let prompt_ttl = if current_config.ttl == 0 {
         600
     } else {
         current_config.ttl
     };
-    let mut ttl = prompt_ttl;
-    if !quick {
+    let mut ttl = ttl.unwrap_or(prompt_ttl);
+    if !quick && ttl_flag_missing {
         ttl = match prompt_user_for_input(
             "Default Ttl Seconds",
             prompt_ttl.to_string().as_str(),
@@ -134,93 +347,41 @@ async fn prompt_user_for_config(quick: bool, profile_name: &str) -> Result<Confi
         };
     }
 
+    // Most profiles use the default endpoint, so only ask while other values are being prompted
+    // for; otherwise supplying the cache and TTL would no longer be enough to skip the prompts.
+    let prompting = !quick && (cache.is_none() || ttl_flag_missing);
+    let endpoint_flag_missing = endpoint.is_none();
+    let mut endpoint = endpoint.or(current_config.endpoint);
+    if prompting && endpoint_flag_missing {
+        let input = prompt_user_for_input(
+            "Endpoint (leave blank for the default)",
+            endpoint.as_deref().unwrap_or_default(),
+            false,
+        )
+        .await?;
+        endpoint = Some(input);
+    }
+
     Ok(Config {
         cache: cache_name_to_use,
         ttl,
+        endpoint: endpoint.filter(|e| !e.is_empty()),
+        output: current_config.output,
+        request_timeout_seconds: current_config.request_timeout_seconds,
     })
 }
 
-#[cfg(target_os = "linux")]
-async fn set_file_read_write(path: &str) -> Result<(), CliError> {
-    use std::os::unix::fs::PermissionsExt;
-    let mut perms = match fs::metadata(path).await {
-        Ok(p) => p,
-        Err(e) => {
-            return Err(CliError {
-                msg: format!("failed to get file permissions {e}"),
-            })
-        }
-    }
-    .permissions();
-    perms.set_mode(0o600);
-    match fs::set_permissions(path, perms).await {
-        Ok(_) => Ok(()),
-        Err(e) => Err(CliError {
-            msg: format!("failed to set file permissions {e}"),
-        }),
-    }
-}
-
-#[cfg(target_os = "macos")]
-async fn set_file_read_write(path: &str) -> Result<(), CliError> {
-    use std::os::unix::fs::PermissionsExt;
-    let mut perms = match fs::metadata(path).await {
-        Ok(p) => p,
-        Err(e) => {
-            return Err(CliError {
-                msg: format!("failed to get file permissions {e}"),
-            })
-        }
-    }
-    .permissions();
-    perms.set_mode(0o600);
-    match fs::set_permissions(path, perms).await {
-        Ok(_) => Ok(()),
-        Err(e) => Err(CliError {
-            msg: format!("failed to set file permissions {e}"),
-        }),
-    }
-}
-
-#[cfg(target_os = "windows")]
-async fn set_file_read_write(path: &str) -> Result<(), CliError> {
-    let mut perms = match fs::metadata(path).await {
-        Ok(p) => p,
-        Err(e) => {
-            return Err(CliError {
-                msg: format!("failed to get file permissions {e}"),
-            })
-        }
-    }
-    .permissions();
-    perms.set_readonly(false);
-    match fs::set_permissions(path, perms).await {
-        Ok(_) => Ok(()),
-        Err(e) => Err(CliError {
-            msg: format!("failed to set file permissions {e}"),
-        }),
-    }
-}
-
-async fn ensure_file_exists_and_get_contents(path: &str) -> Result<Vec<String>, CliError> {
-    if !Path::new(path).exists() {
+async fn ensure_file_exists_and_get_contents(path: &Path) -> Result<Vec<String>, CliError> {
+    if !path.exists() {
         match create_file(path).await {
             Ok(_) => {}
             Err(e) => return Err(e),
         }
     }
-    // explicitly allowing read/write access to the file
-    set_file_read_write(path).await?;
-
     let file = open_file(path).await?;
     read_file_contents(file).await
 }
 
-fn lines_to_file_content(lines: Vec<String>) -> String {
-    // ensure a single trailing newline
-    format!("{}\n", lines.join("\n").trim_end())
-}
-
 fn trim_file_contents(lines: Vec<String>) -> Vec<String> {
     // This is dumb and inefficient but we can optimize it later if necessary
     let content = lines.join("\n");
@@ -298,7 +459,9 @@ fn add_new_config_profile(
 
 #[cfg(test)]
 mod tests {
-    use crate::commands::configure::configure_cli::add_or_update_credentials_profile;
+    use crate::commands::configure::configure_cli::{
+        add_or_update_credentials_profile, pick_cache, resolve_token_flags,
+    };
     use crate::config::Credentials;
 
     fn test_file_content(untrimmed_file_contents: &str) -> String {
@@ -319,7 +482,7 @@ mod tests {
         let updated = add_or_update_credentials_profile(
             "default",
             Credentials {
-                token: "awesome-token".to_string(),
+                token: "awesome-token".into(),
             },
             existing_content,
         )
@@ -339,7 +502,7 @@ token=awesome-token
         let updated = add_or_update_credentials_profile(
             "default",
             Credentials {
-                token: "awesome-token".to_string(),
+                token: "awesome-token".into(),
             },
             existing_content,
         )
@@ -366,7 +529,7 @@ token=old-token
             add_or_update_credentials_profile(
                 "default",
                 Credentials {
-                    token: "old-token".to_string()
+                    token: "old-token".into()
                 },
                 existing_content
             )
@@ -376,7 +539,7 @@ token=old-token
         let updated2 = add_or_update_credentials_profile(
             "default",
             Credentials {
-                token: "old-token".to_string(),
+                token: "old-token".into(),
             },
             test_content_to_lines(&updated1),
         )
@@ -401,7 +564,7 @@ token=old-token
         let updated = add_or_update_credentials_profile(
             "default",
             Credentials {
-                token: "awesome-token".to_string(),
+                token: "awesome-token".into(),
             },
             existing_content,
         )
@@ -426,7 +589,7 @@ token=
         let updated = add_or_update_credentials_profile(
             "default",
             Credentials {
-                token: "awesome-token".to_string(),
+                token: "awesome-token".into(),
             },
             existing_content,
         )
@@ -439,4 +602,60 @@ token=awesome-token
         );
         assert_eq!(expected.trim_end(), updated.join("\n"));
     }
+
+    fn test_env(name: &str) -> Result<String, std::env::VarError> {
+        match name {
+            "TEST_TOKEN_ENV" => Ok("env-token".to_string()),
+            _ => Err(std::env::VarError::NotPresent),
+        }
+    }
+
+    #[tokio::test]
+    async fn resolve_token_flags_trims_literal_token() {
+        let token =
+            resolve_token_flags(Some("  awesome-token\n".to_string()), None, None, test_env)
+                .await
+                .expect("d'oh");
+        assert_eq!(Some("awesome-token".to_string()), token);
+    }
+
+    #[tokio::test]
+    async fn resolve_token_flags_reads_environment_variable() {
+        let token = resolve_token_flags(None, None, Some("TEST_TOKEN_ENV".to_string()), test_env)
+            .await
+            .expect("d'oh");
+        assert_eq!(Some("env-token".to_string()), token);
+    }
+
+    #[tokio::test]
+    async fn resolve_token_flags_rejects_missing_and_empty_tokens() {
+        assert!(
+            resolve_token_flags(None, None, Some("TEST_UNSET_ENV".to_string()), test_env)
+                .await
+                .is_err()
+        );
+        assert!(
+            resolve_token_flags(Some(" ".to_string()), None, None, test_env)
+                .await
+                .is_err()
+        );
+        assert_eq!(
+            None,
+            resolve_token_flags(None, None, None, test_env)
+                .await
+                .expect("d'oh")
+        );
+    }
+
+    #[test]
+    fn pick_cache_by_number_or_new_name() {
+        let caches = vec!["alpha".to_string(), "beta".to_string()];
+        assert_eq!("alpha", pick_cache("1", &caches));
+        assert_eq!("beta", pick_cache("2", &caches));
+        assert_eq!("3", pick_cache("3", &caches));
+        assert_eq!("0", pick_cache("0", &caches));
+        assert_eq!("2024", pick_cache("2024", &caches));
+        assert_eq!("gamma", pick_cache("gamma", &caches));
+        assert_eq!("1", pick_cache("1", &[]));
+    }
 }
@@ -0,0 +1,121 @@
+use std::path::PathBuf;
+
+use crate::{
+    error::CliError,
+    utils::{
+        console::console_info,
+        encryption::{
+            cache_key, forget_key, key_for_passphrase, new_key, parse_encrypted_file,
+            prompt_for_passphrase, read_protected_lines, unlock_seconds, write_encrypted,
+            EncryptedFile, DEFAULT_UNLOCK_COMMAND_SECONDS,
+        },
+        file::{
+            get_credentials_file_path, lines_to_file_content, lock_config_dir,
+            read_file_lines_if_exists, write_to_file,
+        },
+        toml_config::read_toml_config,
+    },
+};
+
+/// The path and lines of the credentials file, which must exist.
+async fn read_credentials_file() -> Result<(PathBuf, Vec<String>), CliError> {
+    let path = get_credentials_file_path()?;
+    if !path.exists() {
+        return Err(CliError {
+            msg: format!(
+                "{} does not exist, please run 'momento configure' to configure your profile",
+                path.display()
+            ),
+        });
+    }
+    let lines = read_file_lines_if_exists(&path).await?;
+    Ok((path, lines))
+}
+
+async fn read_encrypted_credentials_file() -> Result<(PathBuf, EncryptedFile), CliError> {
+    let (path, lines) = read_credentials_file().await?;
+    match parse_encrypted_file(&lines.join("\n")) {
+        Some(file) => Ok((path, file)),
+        None => Err(CliError {
+            msg: format!("{} is not encrypted", path.display()),
+        }),
+    }
+}
+
+/// config.toml is never encrypted, so the tokens in it stay readable.
+async fn warn_about_tokens_in_toml_config() -> Result<(), CliError> {
+    let Some(toml_config) = read_toml_config().await? else {
+        return Ok(());
+    };
+    let profiles: Vec<&str> = toml_config
+        .profiles
+        .iter()
+        .filter(|(_, settings)| settings.token.is_some())
+        .map(|(name, _)| name.as_str())
+        .collect();
+    if !profiles.is_empty() {
+        console_info!(
+            "Warning: config.toml still holds the tokens of profile(s) {} unencrypted",
+            profiles.join(", ")
+        );
+    }
+    Ok(())
+}
+
+pub async fn encrypt_credentials() -> Result<(), CliError> {
+    let _lock = lock_config_dir().await?;
+    let (path, lines) = read_credentials_file().await?;
+    if parse_encrypted_file(&lines.join("\n")).is_some() {
+        return Err(CliError {
+            msg: format!("{} is already encrypted", path.display()),
+        });
+    }
+    let passphrase = prompt_for_passphrase("New passphrase")?;
+    if passphrase.is_empty() {
+        return Err(CliError {
+            msg: "the passphrase cannot be empty".to_string(),
+        });
+    }
+    if prompt_for_passphrase("Repeat the passphrase")? != passphrase {
+        return Err(CliError {
+            msg: "the passphrases do not match".to_string(),
+        });
+    }
+    let (kdf, key) = new_key(passphrase.expose_secret())?;
+    write_encrypted(&path, &lines_to_file_content(lines), kdf.clone(), &key).await?;
+    cache_key(&kdf, &key, unlock_seconds()?.unwrap_or(0)).await?;
+    console_info!("Encrypted {}", path.display());
+    warn_about_tokens_in_toml_config().await
+}
+
+pub async fn decrypt_credentials() -> Result<(), CliError> {
+    let _lock = lock_config_dir().await?;
+    let (path, _) = read_encrypted_credentials_file().await?;
+    let lines = read_protected_lines(&path).await?;
+    write_to_file(&path, lines_to_file_content(lines)).await?;
+    forget_key().await?;
+    console_info!("Decrypted {}", path.display());
+    Ok(())
+}
+
+pub async fn unlock_credentials(seconds: Option<u32>) -> Result<(), CliError> {
+    let (path, file) = read_encrypted_credentials_file().await?;
+    let passphrase = prompt_for_passphrase(&format!("Passphrase for {}", path.display()))?;
+    let key = key_for_passphrase(&file, passphrase.expose_secret())?;
+    let seconds = match seconds {
+        Some(seconds) => seconds,
+        None => unlock_seconds()?.unwrap_or(DEFAULT_UNLOCK_COMMAND_SECONDS),
+    };
+    cache_key(&file.kdf, &key, seconds).await?;
+    console_info!("Unlocked {} for {seconds} seconds", path.display());
+    Ok(())
+}
+
+pub async fn lock_credentials() -> Result<(), CliError> {
+    if forget_key().await? {
+        console_info!("Locked the credentials file");
+    } else {
+        console_info!("The credentials file was not unlocked");
+    }
+    Ok(())
+}
@@ -0,0 +1,384 @@
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::Utc;
+use colored::Colorize;
+use serde::Serialize;
+use tokio::time::Instant;
+
+use crate::{
+    commands::profile::profile_cli::all_profile_names,
+    error::CliError,
+    utils::{
+        api_key::decode_api_key,
+        client::{get_momento_client, print_whatever_this_is_as_json},
+        console::console_data,
+        file::{
+            get_credentials_file_path, get_momento_config_dir, get_sessions_file_path,
+            get_toml_config_file_path,
+        },
+        secret::SecretString,
+        user::{get_config_for_profile, get_creds_and_config, get_creds_for_profile},
+    },
+};
+
+const NETWORK_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Check {
+    pub name: String,
+    pub status: CheckStatus,
+    pub detail: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u128>,
+}
+
+impl Check {
+    fn new(name: impl Into<String>, status: CheckStatus, detail: impl Into<String>) -> Self {
+        Check {
+            name: name.into(),
+            status,
+            detail: detail.into(),
+            duration_ms: None,
+        }
+    }
+
+    fn timed(self, duration: Duration) -> Self {
+        Check {
+            duration_ms: Some(duration.as_millis()),
+            ..self
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct DoctorReport {
+    pub profile: String,
+    pub checks: Vec<Check>,
+}
+
+async fn timed<T>(future: impl Future<Output = T>) -> (T, Duration) {
+    let start = Instant::now();
+    let result = future.await;
+    (result, start.elapsed())
+}
+
+#[cfg(unix)]
+fn file_mode(path: &Path) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::metadata(path)
+        .ok()
+        .map(|metadata| metadata.permissions().mode() & 0o777)
+}
+
+#[cfg(not(unix))]
+fn file_mode(_path: &Path) -> Option<u32> {
+    None
+}
+
+/// Files holding tokens should only be readable by their owner.
+fn token_file_status(mode: u32) -> CheckStatus {
+    if mode & 0o044 == 0 {
+        CheckStatus::Pass
+    } else {
+        CheckStatus::Warn
+    }
+}
+
+fn check_config_dir() -> Check {
+    let name = "config directory";
+    let dir = match get_momento_config_dir() {
+        Ok(dir) => dir,
+        Err(e) => return Check::new(name, CheckStatus::Fail, e.msg),
+    };
+    if !dir.is_dir() {
+        return Check::new(
+            name,
+            CheckStatus::Fail,
+            format!(
+                "{} does not exist, please run 'momento configure'",
+                dir.display()
+            ),
+        );
+    }
+    let dir_display = dir.display();
+    match file_mode(&dir) {
+        Some(mode) => Check::new(
+            name,
+            CheckStatus::Pass,
+            format!("{dir_display} exists with mode {mode:o}"),
+        ),
+        None => Check::new(name, CheckStatus::Pass, format!("{dir_display} exists")),
+    }
+}
+
+fn check_token_file(name: &str, path: Result<PathBuf, CliError>) -> Option<Check> {
+    let path = match path {
+        Ok(path) => path,
+        Err(e) => return Some(Check::new(name, CheckStatus::Fail, e.msg)),
+    };
+    if !path.exists() {
+        return None;
+    }
+    let mode = file_mode(&path)?;
+    let path = path.display();
+    Some(match token_file_status(mode) {
+        CheckStatus::Pass => Check::new(
+            name,
+            CheckStatus::Pass,
+            format!("{path} is only readable by its owner (mode {mode:o})"),
+        ),
+        status => Check::new(
+            name,
+            status,
+            format!(
+                "{path} is readable by group or others (mode {mode:o}), run 'chmod 600 {path}'"
+            ),
+        ),
+    })
+}
+
+async fn check_profile(profile: &str) -> Check {
+    let name = format!("profile {profile}");
+    let config = match get_config_for_profile(profile).await {
+        Ok(config) => config,
+        Err(e) => return Check::new(name, CheckStatus::Fail, e.msg),
+    };
+    let token = match get_creds_for_profile(profile).await {
+        Ok(credentials) => credentials.token,
+        Err(e) => return Check::new(name, CheckStatus::Fail, e.msg),
+    };
+    let settings = format!("cache {}, ttl {}s", config.cache, config.ttl);
+    match decode_api_key(token.expose_secret()) {
+        Ok(info) => match (info.expires_at, info.expiry_warning(Utc::now())) {
+            (Some(expires_at), _) if expires_at <= Utc::now() => Check::new(
+                name,
+                CheckStatus::Fail,
+                format!("{settings}, token expired at {expires_at}"),
+            ),
+            (_, Some(warning)) => {
+                Check::new(name, CheckStatus::Warn, format!("{settings}, {warning}"))
+            }
+            (Some(expires_at), None) => Check::new(
+                name,
+                CheckStatus::Pass,
+                format!("{settings}, token expires at {expires_at}"),
+            ),
+            (None, None) => Check::new(
+                name,
+                CheckStatus::Pass,
+                format!("{settings}, token never expires"),
+            ),
+        },
+        Err(e) => Check::new(
+            name,
+            CheckStatus::Warn,
+            format!("{settings}, token could not be decoded: {}", e.msg),
+        ),
+    }
+}
+
+async fn check_dns(host: &str) -> Check {
+    let name = format!("dns {host}");
+    let (resolved, duration) = timed(tokio::time::timeout(
+        NETWORK_TIMEOUT,
+        tokio::net::lookup_host((host, 443)),
+    ))
+    .await;
+    let check = match resolved {
+        Ok(Ok(addresses)) => match addresses.map(|a| a.ip().to_string()).next() {
+            Some(address) => Check::new(name, CheckStatus::Pass, format!("resolved to {address}")),
+            None => Check::new(name, CheckStatus::Fail, "resolved to no addresses"),
+        },
+        Ok(Err(e)) => Check::new(name, CheckStatus::Fail, format!("failed to resolve: {e}")),
+        Err(_) => Check::new(name, CheckStatus::Fail, "timed out"),
+    };
+    check.timed(duration)
+}
+
+async fn check_tls(host: &str) -> Check {
+    let name = format!("tls {host}");
+    let client = match reqwest::Client::builder().timeout(NETWORK_TIMEOUT).build() {
+        Ok(client) => client,
+        Err(e) => return Check::new(name, CheckStatus::Fail, e.to_string()),
+    };
+    let (response, duration) = timed(client.get(format!("https://{host}")).send()).await;
+    let check = match response {
+        Ok(response) => Check::new(
+            name,
+            CheckStatus::Pass,
+            format!("connected, HTTP {}", response.status()),
+        ),
+        Err(e) if e.is_connect() || e.is_timeout() => Check::new(
+            name,
+            CheckStatus::Fail,
+            format!("failed to connect: {}", error_chain(&e)),
+        ),
+        // Endpoints that only speak gRPC may reject a plain HTTPS request, so without a
+        // response it is unclear whether the handshake succeeded.
+        Err(e) => Check::new(
+            name,
+            CheckStatus::Warn,
+            format!("no HTTP response: {}", error_chain(&e)),
+        ),
+    };
+    check.timed(duration)
+}
+
+/// `e` followed by its causes, which reqwest leaves out of its own message.
+fn error_chain(e: &dyn std::error::Error) -> String {
+    let mut message = e.to_string();
+    let mut source = e.source();
+    while let Some(cause) = source {
+        message.push_str(&format!(": {cause}"));
+        source = cause.source();
+    }
+    message
+}
+
+async fn check_default_cache(token: SecretString, endpoint: Option<String>, cache: &str) -> Check {
+    let name = "default cache";
+    let list_caches = async {
+        let mut client = get_momento_client(token, endpoint).await?;
+        Ok::<_, CliError>(client.list_caches(None).await?)
+    };
+    let (listed, duration) = timed(tokio::time::timeout(NETWORK_TIMEOUT, list_caches)).await;
+    let check = match listed {
+        Ok(Ok(list)) if list.caches.iter().any(|c| c.cache_name == cache) => {
+            Check::new(name, CheckStatus::Pass, format!("{cache} exists"))
+        }
+        Ok(Ok(_)) => Check::new(
+            name,
+            CheckStatus::Fail,
+            format!("{cache} does not exist, run 'momento cache create {cache}'"),
+        ),
+        Ok(Err(e)) => Check::new(name, CheckStatus::Fail, e.msg),
+        Err(_) => Check::new(name, CheckStatus::Fail, "timed out listing caches"),
+    };
+    check.timed(duration)
+}
+
+async fn run_checks(profile: &str) -> Vec<Check> {
+    let mut checks = vec![check_config_dir()];
+    checks.extend(check_token_file(
+        "credentials file",
+        get_credentials_file_path(),
+    ));
+    checks.extend(check_token_file("config.toml", get_toml_config_file_path()));
+    checks.extend(check_token_file("sessions file", get_sessions_file_path()));
+
+    match all_profile_names().await {
+        Ok(profiles) if profiles.is_empty() => checks.push(Check::new(
+            "profiles",
+            CheckStatus::Warn,
+            "no profiles found, please run 'momento configure'",
+        )),
+        Ok(profiles) => {
+            for name in profiles {
+                checks.push(check_profile(&name).await);
+            }
+        }
+        Err(e) => checks.push(Check::new("profiles", CheckStatus::Fail, e.msg)),
+    }
+
+    let (credentials, config) = match get_creds_and_config(profile).await {
+        Ok(creds_and_config) => creds_and_config,
+        Err(e) => {
+            checks.push(Check::new(
+                "network",
+                CheckStatus::Fail,
+                format!("skipped, profile {profile} cannot be used: {}", e.msg),
+            ));
+            return checks;
+        }
+    };
+    let endpoint = config.endpoint.clone().or_else(|| {
+        decode_api_key(credentials.token.expose_secret())
+            .ok()
+            .and_then(|info| info.endpoint)
+    });
+    match &endpoint {
+        Some(endpoint) => {
+            for host in [format!("control.{endpoint}"), format!("cache.{endpoint}")] {
+                checks.push(check_dns(&host).await);
+                checks.push(check_tls(&host).await);
+            }
+        }
+        None => checks.push(Check::new(
+            "network",
+            CheckStatus::Warn,
+            format!("skipped, no endpoint is known for profile {profile}"),
+        )),
+    }
+    checks.push(check_default_cache(credentials.token, config.endpoint, &config.cache).await);
+    checks
+}
+
+fn print_check(check: &Check) {
+    let status = match check.status {
+        CheckStatus::Pass => "PASS".green(),
+        CheckStatus::Warn => "WARN".yellow(),
+        CheckStatus::Fail => "FAIL".red(),
+    };
+    match check.duration_ms {
+        Some(ms) => console_data!("[{status}] {}: {} ({ms}ms)", check.name, check.detail),
+        None => console_data!("[{status}] {}: {}", check.name, check.detail),
+    }
+}
+
+/// Diagnose the CLI setup of `profile`, failing if any check fails.
+pub async fn run_doctor(profile: &str, json: bool) -> Result<(), CliError> {
+    let report = DoctorReport {
+        profile: profile.to_string(),
+        checks: run_checks(profile).await,
+    };
+    if json {
+        print_whatever_this_is_as_json(&report);
+    } else {
+        report.checks.iter().for_each(print_check);
+    }
+
+    let failed = report
+        .checks
+        .iter()
+        .filter(|check| check.status == CheckStatus::Fail)
+        .count();
+    if failed > 0 {
+        return Err(CliError {
+            msg: format!("{failed} check(s) failed"),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::doctor::doctor_cli::{token_file_status, Check, CheckStatus};
+
+    #[test]
+    fn token_files_readable_by_others_are_warned_about() {
+        assert_eq!(CheckStatus::Pass, token_file_status(0o600));
+        assert_eq!(CheckStatus::Pass, token_file_status(0o700));
+        assert_eq!(CheckStatus::Warn, token_file_status(0o640));
+        assert_eq!(CheckStatus::Warn, token_file_status(0o604));
+    }
+
+    #[test]
+    fn checks_serialize_for_support_tickets() {
+        let check = Check::new("dns cache.example.com", CheckStatus::Pass, "resolved")
+            .timed(std::time::Duration::from_millis(12));
+        assert_eq!(
+            r#"{"name":"dns cache.example.com","status":"pass","detail":"resolved","duration_ms":12}"#,
+            serde_json::to_string(&check).expect("d'oh")
+        );
+    }
+}
@@ -0,0 +1,132 @@
+use momento_cli_opts::EnvFormat;
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::{
+    config::{
+        ENV_VAR_NAME_MOMENTO_API_KEY, ENV_VAR_NAME_MOMENTO_CACHE, ENV_VAR_NAME_MOMENTO_ENDPOINT,
+    },
+    error::CliError,
+    utils::{console::console_data, user::get_creds_and_config},
+};
+
+/// A double quoted string, which is valid JSON, YAML and dotenv.
+fn double_quoted(value: &str) -> String {
+    serde_json::Value::String(value.to_string()).to_string()
+}
+
+/// Render `vars` so that another tool can load them.
+pub fn format_env(vars: &[(&str, String)], format: EnvFormat, secret_name: &str) -> String {
+    let lines: Zeroizing<Vec<String>> = Zeroizing::new(match format {
+        EnvFormat::Sh => vars
+            .iter()
+            .map(|(name, value)| format!("export {name}='{}'", value.replace('\'', r"'\''")))
+            .collect(),
+        EnvFormat::Fish => vars
+            .iter()
+            .map(|(name, value)| {
+                let escaped = value.replace('\\', r"\\").replace('\'', r"\'");
+                format!("set -gx {name} '{escaped}'")
+            })
+            .collect(),
+        EnvFormat::Powershell => vars
+            .iter()
+            .map(|(name, value)| format!("$env:{name} = '{}'", value.replace('\'', "''")))
+            .collect(),
+        EnvFormat::Dotenv => vars
+            .iter()
+            .map(|(name, value)| format!("{name}={}", double_quoted(value)))
+            .collect(),
+        EnvFormat::Json => {
+            let object: serde_json::Map<String, serde_json::Value> = vars
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.clone().into()))
+                .collect();
+            vec![serde_json::to_string_pretty(&object).expect("a map of strings is valid json")]
+        }
+        EnvFormat::K8sSecret => {
+            let mut lines = vec![
+                "apiVersion: v1".to_string(),
+                "kind: Secret".to_string(),
+                "metadata:".to_string(),
+                format!("  name: {}", double_quoted(secret_name)),
+                "type: Opaque".to_string(),
+                "stringData:".to_string(),
+            ];
+            lines.extend(
+                vars.iter()
+                    .map(|(name, value)| format!("  {name}: {}", double_quoted(value))),
+            );
+            lines
+        }
+    });
+    lines.join("\n")
+}
+
+pub async fn print_env(
+    profile: &str,
+    format: EnvFormat,
+    secret_name: &str,
+) -> Result<(), CliError> {
+    let (creds, config) = get_creds_and_config(profile).await?;
+    let mut vars = vec![(
+        ENV_VAR_NAME_MOMENTO_API_KEY,
+        creds.token.expose_secret().to_string(),
+    )];
+    if let Some(endpoint) = config.endpoint {
+        vars.push((ENV_VAR_NAME_MOMENTO_ENDPOINT, endpoint));
+    }
+    vars.push((ENV_VAR_NAME_MOMENTO_CACHE, config.cache));
+    let rendered = Zeroizing::new(format_env(&vars, format, secret_name));
+    console_data!("{}", *rendered);
+    // The copies of the key are not SecretStrings, so they are zeroed by hand.
+    for (_, value) in &mut vars {
+        value.zeroize();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use momento_cli_opts::EnvFormat;
+
+    use crate::commands::env::env_cli::format_env;
+
+    fn vars() -> Vec<(&'static str, String)> {
+        vec![
+            ("MOMENTO_API_KEY", "to'ken".to_string()),
+            ("MOMENTO_CACHE", "default-cache".to_string()),
+        ]
+    }
+
+    #[test]
+    fn format_env_for_shells() {
+        assert_eq!(
+            "export MOMENTO_API_KEY='to'\\''ken'\nexport MOMENTO_CACHE='default-cache'",
+            format_env(&vars(), EnvFormat::Sh, "momento")
+        );
+        assert_eq!(
+            "set -gx MOMENTO_API_KEY 'to\\'ken'\nset -gx MOMENTO_CACHE 'default-cache'",
+            format_env(&vars(), EnvFormat::Fish, "momento")
+        );
+        assert_eq!(
+            "$env:MOMENTO_API_KEY = 'to''ken'\n$env:MOMENTO_CACHE = 'default-cache'",
+            format_env(&vars(), EnvFormat::Powershell, "momento")
+        );
+    }
+
+    #[test]
+    fn format_env_for_files() {
+        assert_eq!(
+            "MOMENTO_API_KEY=\"to'ken\"\nMOMENTO_CACHE=\"default-cache\"",
+            format_env(&vars(), EnvFormat::Dotenv, "momento")
+        );
+        assert_eq!(
+            "{\n  \"MOMENTO_API_KEY\": \"to'ken\",\n  \"MOMENTO_CACHE\": \"default-cache\"\n}",
+            format_env(&vars(), EnvFormat::Json, "momento")
+        );
+        assert_eq!(
+            "apiVersion: v1\nkind: Secret\nmetadata:\n  name: \"app-momento\"\ntype: Opaque\nstringData:\n  MOMENTO_API_KEY: \"to'ken\"\n  MOMENTO_CACHE: \"default-cache\"",
+            format_env(&vars(), EnvFormat::K8sSecret, "app-momento")
+        );
+    }
+}
@@ -1,7 +1,13 @@
 pub mod account;
 pub mod cache;
+pub mod config;
 pub mod configure;
+pub mod credentials;
+pub mod doctor;
+pub mod env;
 pub mod login;
+pub mod profile;
+pub mod session;
 pub mod signingkey;
 pub mod tokens;
 
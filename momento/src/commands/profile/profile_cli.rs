@@ -0,0 +1,326 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use crate::{
+    config::CONFIG_KEYS,
+    error::CliError,
+    utils::{
+        console::{console_data, console_info},
+        encryption::{read_protected_lines, write_protected_lines},
+        file::{
+            get_config_file_path, get_credentials_file_path, get_sessions_file_path,
+            lock_config_dir,
+        },
+        ini_config::{
+            copy_profile, delete_profile, list_profile_names, profiles_inheriting_from,
+            rename_profile,
+        },
+        toml_config::{read_toml_config, write_toml_config, ProfileSettings},
+        user::{
+            get_creds_for_profile, get_current_profile, get_profile_settings, session_section,
+            set_current_profile,
+        },
+    },
+};
+
+/// The credentials, config and login sessions files, which every profile command keeps
+/// in step.
+fn profile_file_paths() -> Result<[PathBuf; 3], CliError> {
+    Ok([
+        get_credentials_file_path()?,
+        get_config_file_path()?,
+        get_sessions_file_path()?,
+    ])
+}
+
+/// Profile names from config.toml and both INI files, in the order they first appear.
+pub async fn all_profile_names() -> Result<Vec<String>, CliError> {
+    let mut names: Vec<String> = match read_toml_config().await? {
+        Some(toml_config) => toml_config.profiles.into_keys().collect(),
+        None => vec![],
+    };
+    for path in profile_file_paths()? {
+        for name in list_profile_names(&read_protected_lines(&path).await?) {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+    Ok(names)
+}
+
+async fn ensure_profile_exists(profile_name: &str) -> Result<(), CliError> {
+    if all_profile_names().await?.iter().any(|n| n == profile_name) {
+        Ok(())
+    } else {
+        Err(CliError {
+            msg: format!("profile {profile_name} does not exist"),
+        })
+    }
+}
+
+async fn ensure_profile_does_not_exist(profile_name: &str) -> Result<(), CliError> {
+    if all_profile_names().await?.iter().any(|n| n == profile_name) {
+        Err(CliError {
+            msg: format!("profile {profile_name} already exists"),
+        })
+    } else {
+        Ok(())
+    }
+}
+
+/// The profiles in config.toml and both INI files that inherit from `profile_name`.
+async fn child_profiles(profile_name: &str) -> Result<Vec<String>, CliError> {
+    let mut children: Vec<String> = match read_toml_config().await? {
+        Some(toml_config) => toml_config
+            .profiles
+            .into_iter()
+            .filter(|(_, settings)| settings.inherits.as_deref() == Some(profile_name))
+            .map(|(name, _)| name)
+            .collect(),
+        None => vec![],
+    };
+    for path in profile_file_paths()? {
+        for name in profiles_inheriting_from(profile_name, &read_protected_lines(&path).await?) {
+            if !children.contains(&name) {
+                children.push(name);
+            }
+        }
+    }
+    Ok(children)
+}
+
+/// Delete the profile, and the `inherits` of the profiles that inherited from it.
+fn delete_toml_profile(profiles: &mut BTreeMap<String, ProfileSettings>, profile_name: &str) {
+    profiles.remove(profile_name);
+    for settings in profiles.values_mut() {
+        if settings.inherits.as_deref() == Some(profile_name) {
+            settings.inherits = None;
+        }
+    }
+}
+
+/// Rename the profile, and point the profiles that inherited from it at the new name.
+fn rename_toml_profile(
+    profiles: &mut BTreeMap<String, ProfileSettings>,
+    profile_name: &str,
+    new_name: &str,
+) {
+    if let Some(settings) = profiles.remove(profile_name) {
+        profiles.insert(new_name.to_string(), settings);
+    }
+    for settings in profiles.values_mut() {
+        if settings.inherits.as_deref() == Some(profile_name) {
+            settings.inherits = Some(new_name.to_string());
+        }
+    }
+}
+
+/// Apply `edit_toml` to the profiles in config.toml and `edit` to every INI profile
+/// file, skipping the files that do not exist.
+async fn edit_profile_files(
+    edit: impl Fn(&[String]) -> Vec<String>,
+    edit_toml: impl FnOnce(&mut BTreeMap<String, ProfileSettings>),
+) -> Result<(), CliError> {
+    let _lock = lock_config_dir().await?;
+    if let Some(mut toml_config) = read_toml_config().await? {
+        edit_toml(&mut toml_config.profiles);
+        write_toml_config(&toml_config).await?;
+    }
+    for path in profile_file_paths()? {
+        if !path.exists() {
+            continue;
+        }
+        let file_contents = read_protected_lines(&path).await?;
+        write_protected_lines(&path, edit(&file_contents)).await?;
+    }
+    Ok(())
+}
+
+fn redact_token(token: &str) -> String {
+    let visible = 4;
+    if token.chars().count() <= visible * 3 {
+        return "****".to_string();
+    }
+    let suffix: String = token
+        .chars()
+        .skip(token.chars().count() - visible)
+        .collect();
+    format!("****{suffix}")
+}
+
+pub async fn list_profiles() -> Result<(), CliError> {
+    let current_profile = get_current_profile().await;
+    for name in all_profile_names().await? {
+        let marker = if current_profile.as_deref() == Some(name.as_str()) {
+            "*"
+        } else {
+            " "
+        };
+        console_data!("{marker} {name}");
+    }
+    Ok(())
+}
+
+pub async fn show_profile(profile_name: &str) -> Result<(), CliError> {
+    ensure_profile_exists(profile_name).await?;
+    let token = get_creds_for_profile(profile_name)
+        .await
+        .map(|creds| redact_token(creds.token.expose_secret()))
+        .unwrap_or_else(|_| "<not set>".to_string());
+    let settings = match get_profile_settings(profile_name).await {
+        Ok(settings) => settings,
+        Err(e) => {
+            console_info!("Warning: {}", e.msg);
+            BTreeMap::new()
+        }
+    };
+    console_data!("profile: {profile_name}");
+    console_data!("token: {token}");
+    for key in CONFIG_KEYS {
+        match settings.get(key) {
+            Some(setting) if setting.source != profile_name => {
+                console_data!("{key}: {} (from {})", setting.value, setting.source)
+            }
+            Some(setting) => console_data!("{key}: {}", setting.value),
+            None if key == "endpoint" => console_data!("{key}: <default>"),
+            None => console_data!("{key}: <not set>"),
+        }
+    }
+    Ok(())
+}
+
+/// Delete the profile. Profiles that inherit from it would lose the settings they take
+/// from it, so they stop the deletion unless `force` is set.
+pub async fn delete_profile_everywhere(profile_name: &str, force: bool) -> Result<(), CliError> {
+    ensure_profile_exists(profile_name).await?;
+    let children = child_profiles(profile_name).await?;
+    if !children.is_empty() && !force {
+        return Err(CliError {
+            msg: format!(
+                "profiles {} inherit from profile {profile_name}, pass --force to delete it anyway",
+                children.join(", ")
+            ),
+        });
+    }
+    edit_profile_files(
+        |contents| {
+            let contents = delete_profile(profile_name, contents);
+            delete_profile(&session_section(profile_name), &contents)
+        },
+        |profiles| delete_toml_profile(profiles, profile_name),
+    )
+    .await?;
+    if !children.is_empty() {
+        console_info!(
+            "Profiles {} no longer inherit from profile {profile_name}",
+            children.join(", ")
+        );
+    }
+    if get_current_profile().await.as_deref() == Some(profile_name) {
+        set_current_profile(None).await?;
+    }
+    console_info!("Deleted profile {profile_name}");
+    Ok(())
+}
+
+pub async fn rename_profile_everywhere(profile_name: &str, new_name: &str) -> Result<(), CliError> {
+    ensure_profile_exists(profile_name).await?;
+    ensure_profile_does_not_exist(new_name).await?;
+    edit_profile_files(
+        |contents| {
+            let contents = rename_profile(profile_name, new_name, contents);
+            rename_profile(
+                &session_section(profile_name),
+                &session_section(new_name),
+                &contents,
+            )
+        },
+        |profiles| rename_toml_profile(profiles, profile_name, new_name),
+    )
+    .await?;
+    if get_current_profile().await.as_deref() == Some(profile_name) {
+        set_current_profile(Some(new_name)).await?;
+    }
+    console_info!("Renamed profile {profile_name} to {new_name}");
+    Ok(())
+}
+
+pub async fn copy_profile_everywhere(profile_name: &str, new_name: &str) -> Result<(), CliError> {
+    ensure_profile_exists(profile_name).await?;
+    ensure_profile_does_not_exist(new_name).await?;
+    edit_profile_files(
+        |contents| copy_profile(profile_name, new_name, contents),
+        |profiles| {
+            if let Some(settings) = profiles.get(profile_name).cloned() {
+                profiles.insert(new_name.to_string(), settings);
+            }
+        },
+    )
+    .await?;
+    console_info!("Copied profile {profile_name} to {new_name}");
+    Ok(())
+}
+
+pub async fn use_profile(profile_name: &str) -> Result<(), CliError> {
+    ensure_profile_exists(profile_name).await?;
+    set_current_profile(Some(profile_name)).await?;
+    console_info!("Now using profile {profile_name}");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use crate::commands::profile::profile_cli::{
+        delete_toml_profile, redact_token, rename_toml_profile,
+    };
+    use crate::utils::toml_config::ProfileSettings;
+
+    fn family() -> BTreeMap<String, ProfileSettings> {
+        let child = |parent: &str| ProfileSettings {
+            inherits: Some(parent.to_string()),
+            ..Default::default()
+        };
+        BTreeMap::from([
+            ("base".to_string(), ProfileSettings::default()),
+            ("team-a".to_string(), child("base")),
+            ("team-b".to_string(), child("team-a")),
+        ])
+    }
+
+    fn inherits(profiles: &BTreeMap<String, ProfileSettings>, name: &str) -> Option<String> {
+        profiles.get(name).expect("d'oh").inherits.clone()
+    }
+
+    #[test]
+    fn rename_toml_profile_updates_inherits() {
+        let mut profiles = family();
+        rename_toml_profile(&mut profiles, "base", "shared");
+        assert!(!profiles.contains_key("base"));
+        assert!(profiles.contains_key("shared"));
+        assert_eq!(Some("shared".to_string()), inherits(&profiles, "team-a"));
+        assert_eq!(Some("team-a".to_string()), inherits(&profiles, "team-b"));
+    }
+
+    #[test]
+    fn delete_toml_profile_removes_inherits() {
+        let mut profiles = family();
+        delete_toml_profile(&mut profiles, "base");
+        assert!(!profiles.contains_key("base"));
+        assert_eq!(None, inherits(&profiles, "team-a"));
+        assert_eq!(Some("team-a".to_string()), inherits(&profiles, "team-b"));
+    }
+
+    #[test]
+    fn redact_token_keeps_only_the_last_characters() {
+        assert_eq!("****6789", redact_token("abcdefghijklmnop0123456789"));
+    }
+
+    #[test]
+    fn redact_token_hides_short_tokens_entirely() {
+        assert_eq!("****", redact_token("short-token"));
+        assert_eq!("****", redact_token(""));
+    }
+}
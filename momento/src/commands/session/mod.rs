@@ -0,0 +1 @@
+pub mod session_cli;
@@ -0,0 +1,68 @@
+use chrono::Utc;
+use momento_cli_opts::LoginMode;
+
+use crate::{
+    commands::login::login,
+    error::CliError,
+    utils::{
+        console::{console_data, console_info},
+        secret::{mask_secrets, SecretString},
+        user::{clobber_session_token, get_session, remove_session},
+    },
+};
+
+/// Log in through the browser or a QR code and keep the session token for `profile`.
+pub async fn start_session(profile: &str, via: LoginMode) -> Result<(), CliError> {
+    match login(via).await {
+        Ok(credentials) => {
+            let session_token = SecretString::new(credentials.token());
+            let session_duration = credentials.valid_for();
+            log::debug!(
+                "Received a session token valid for {}s",
+                session_duration.as_secs()
+            );
+            clobber_session_token(
+                profile,
+                Some(session_token),
+                session_duration.as_secs() as u32,
+            )
+            .await?;
+            console_info!(
+                "Login valid for {}m for profile {profile}",
+                session_duration.as_secs() / 60
+            );
+            Ok(())
+        }
+        Err(auth_error) => Err(CliError {
+            msg: mask_secrets(&format!("auth error: {auth_error:?}")),
+        }),
+    }
+}
+
+pub async fn session_status(profile: &str) -> Result<(), CliError> {
+    match get_session(profile).await? {
+        Some(session) if session.is_valid() => {
+            let remaining = session.valid_until - Utc::now();
+            console_data!(
+                "profile {profile} has a login session valid until {} ({}m remaining)",
+                session.valid_until,
+                remaining.num_minutes()
+            );
+        }
+        Some(session) => console_data!(
+            "the login session of profile {profile} expired at {}",
+            session.valid_until
+        ),
+        None => console_data!("profile {profile} has no login session"),
+    }
+    Ok(())
+}
+
+pub async fn logout(profile: &str) -> Result<(), CliError> {
+    if remove_session(profile).await? {
+        console_info!("Logged out of profile {profile}");
+    } else {
+        console_info!("Profile {profile} has no login session");
+    }
+    Ok(())
+}
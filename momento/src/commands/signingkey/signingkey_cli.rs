@@ -1,11 +1,14 @@
 use crate::{
     error::CliError,
-    utils::client::{get_momento_client, interact_with_momento, print_whatever_this_is_as_json},
+    utils::{
+        client::{get_momento_client, interact_with_momento, print_whatever_this_is_as_json},
+        secret::SecretString,
+    },
 };
 
 pub async fn create_signing_key(
     ttl_minutes: u32,
-    auth_token: String,
+    auth_token: SecretString,
     endpoint: Option<String>,
 ) -> Result<(), CliError> {
     let mut client = get_momento_client(auth_token, endpoint).await?;
@@ -23,7 +26,7 @@ pub async fn create_signing_key(
 
 pub async fn revoke_signing_key(
     key_id: String,
-    auth_token: String,
+    auth_token: SecretString,
     endpoint: Option<String>,
 ) -> Result<(), CliError> {
     let mut client = get_momento_client(auth_token, endpoint).await?;
@@ -36,7 +39,7 @@ pub async fn revoke_signing_key(
 }
 
 pub async fn list_signing_keys(
-    auth_token: String,
+    auth_token: SecretString,
     endpoint: Option<String>,
 ) -> Result<(), CliError> {
     let mut client = get_momento_client(auth_token, endpoint).await?;
@@ -1,33 +1,262 @@
-use momento::requests::generate_api_token_request::TokenExpiry;
+use std::time::SystemTime;
+
+use chrono::{DateTime, Utc};
+use momento::{
+    requests::generate_api_token_request::TokenExpiry, response::MomentoGenerateApiTokenResponse,
+};
+use serde::Serialize;
+use tokio::io::AsyncReadExt;
 
 use crate::{
+    config::OutputFormat,
     error::CliError,
-    utils::client::{get_momento_client, interact_with_momento, print_whatever_this_is_as_json},
+    utils::{
+        api_key::decode_api_key,
+        client::{
+            client_settings, get_momento_client, init_client_settings, interact_with_momento,
+            print_whatever_this_is_as_json,
+        },
+        console::{console_data, console_info},
+        encryption::{read_protected_lines, write_protected_lines},
+        file::{get_credentials_file_path, lock_config_dir},
+        ini_config::{update_generated_token_profile, IniDocument},
+        secret::SecretString,
+        toml_config::{read_toml_config, write_toml_config},
+        user::{get_config_for_profile, get_creds_for_profile},
+    },
 };
 
+/// A newly generated API key. Its tokens are only written out when it is serialized.
+#[derive(Serialize, Debug)]
+pub struct GeneratedApiToken {
+    pub api_token: SecretString,
+    pub refresh_token: SecretString,
+    pub valid_until: SystemTime,
+}
+
+impl From<MomentoGenerateApiTokenResponse> for GeneratedApiToken {
+    fn from(response: MomentoGenerateApiTokenResponse) -> Self {
+        GeneratedApiToken {
+            api_token: response.api_token.into(),
+            refresh_token: response.refresh_token.into(),
+            valid_until: response.valid_until,
+        }
+    }
+}
+
+impl GeneratedApiToken {
+    /// The expiry as stored in a profile, or `None` for tokens that never expire.
+    fn valid_until_rfc3339(&self) -> Option<String> {
+        if self.valid_until == SystemTime::UNIX_EPOCH {
+            return None;
+        }
+        Some(DateTime::<Utc>::from(self.valid_until).to_rfc3339())
+    }
+}
+
+fn token_expiry(never_expire: bool, valid_for: Option<String>) -> Result<TokenExpiry, CliError> {
+    if never_expire {
+        return Ok(TokenExpiry::Never {});
+    }
+    let valid_for = valid_for.expect("oneof --valid-for, or --never-expire, must be set");
+    let seconds = valid_for
+        .parse::<humantime::Duration>()
+        .map_err(|e| CliError {
+            msg: format!("unable to parse valid-for duration {valid_for}: {e}"),
+        })?
+        .as_secs();
+    let valid_for_seconds = u32::try_from(seconds).map_err(|_| CliError {
+        msg: format!(
+            "valid-for duration {valid_for} is too long, it must be at most {} seconds",
+            u32::MAX
+        ),
+    })?;
+    Ok(TokenExpiry::Expires { valid_for_seconds })
+}
+
 pub async fn generate_api_token(
-    auth_token: String,
+    auth_token: SecretString,
     endpoint: Option<String>,
     never_expire: bool,
     valid_for: Option<String>,
+    save_to_profile: Option<String>,
+) -> Result<(), CliError> {
+    let expiry = token_expiry(never_expire, valid_for)?;
+    let mut client = get_momento_client(auth_token, endpoint).await?;
+    let generated: GeneratedApiToken =
+        interact_with_momento("generating api token...", client.generate_api_token(expiry))
+            .await?
+            .into();
+    match save_to_profile {
+        Some(profile) => {
+            let _lock = lock_config_dir().await?;
+            save_generated_token(&profile, &generated).await?;
+            console_info!("Saved the new token to profile {profile}");
+        }
+        None => print_whatever_this_is_as_json(&generated),
+    }
+    Ok(())
+}
+
+/// The token stored in the profile itself, ignoring login sessions, environment variables
+/// and `credential_process`.
+async fn get_stored_token(profile: &str) -> Result<Option<SecretString>, CliError> {
+    if let Some(toml_config) = read_toml_config().await? {
+        if let Some(settings) = toml_config.profiles.get(profile) {
+            return Ok(settings.token.clone().filter(|token| !token.is_empty()));
+        }
+    }
+    let credentials = read_protected_lines(&get_credentials_file_path()?).await?;
+    Ok(IniDocument::parse(&credentials)
+        .get(profile, "token")
+        .filter(|token| !token.is_empty())
+        .map(SecretString::from))
+}
+
+/// Store `generated` in the profile, in `config.toml` once it exists. The caller holds the
+/// config dir lock.
+async fn save_generated_token(
+    profile: &str,
+    generated: &GeneratedApiToken,
+) -> Result<(), CliError> {
+    let valid_until = generated.valid_until_rfc3339();
+    match read_toml_config().await? {
+        Some(mut toml_config) => {
+            toml_config
+                .profiles
+                .entry(profile.to_string())
+                .or_default()
+                .set_generated_token(
+                    generated.api_token.clone(),
+                    generated.refresh_token.clone(),
+                    valid_until,
+                );
+            write_toml_config(&toml_config).await
+        }
+        None => {
+            let path = get_credentials_file_path()?;
+            let credentials = read_protected_lines(&path).await?;
+            let credentials = update_generated_token_profile(
+                profile,
+                &credentials,
+                &generated.api_token,
+                &generated.refresh_token,
+                valid_until.as_deref(),
+            );
+            write_protected_lines(&path, credentials).await
+        }
+    }
+}
+
+/// How long a replacement for `token` should be valid for: as long as `token` was.
+fn same_lifetime_as(token: &SecretString) -> Result<TokenExpiry, CliError> {
+    let info = decode_api_key(token.expose_secret())?;
+    match (info.issued_at, info.expires_at) {
+        (_, None) => Ok(TokenExpiry::Never {}),
+        (Some(issued_at), Some(expires_at)) => {
+            let seconds = (expires_at - issued_at).num_seconds().max(0);
+            let valid_for_seconds = u32::try_from(seconds).map_err(|_| CliError {
+                msg: format!("the token is valid for {seconds} seconds, which is too long to copy, please pass --valid-for or --never-expire"),
+            })?;
+            Ok(TokenExpiry::Expires { valid_for_seconds })
+        }
+        (None, Some(_)) => Err(CliError {
+            msg: "could not tell how long the token is valid for, please pass --valid-for or --never-expire".to_string(),
+        }),
+    }
+}
+
+/// Replace the token of `profile` with a newly generated one once the new token has been
+/// shown to work, keeping the old token as `previous_token`.
+pub async fn rotate_profile_token(
+    profile: &str,
+    never_expire: bool,
+    valid_for: Option<String>,
 ) -> Result<(), CliError> {
-    let expiry = if never_expire {
-        TokenExpiry::Never {}
-    } else {
-        let seconds = valid_for
-            .expect("oneof --valid-for, or --never-expire, must be set")
-            .as_str()
-            .parse::<humantime::Duration>()
-            .expect("unable to parse valid-for duration")
-            .as_secs();
-        TokenExpiry::Expires {
-            valid_for_seconds: seconds as u32,
+    // Held throughout, so that the token cannot change between reading and replacing it.
+    let _lock = lock_config_dir().await?;
+    let previous = get_stored_token(profile).await?.ok_or_else(|| CliError {
+        msg: format!("profile {profile} has no stored token to rotate"),
+    })?;
+    let expiry = match valid_for {
+        None if !never_expire => same_lifetime_as(&previous)?,
+        valid_for => token_expiry(never_expire, valid_for)?,
+    };
+    let endpoint = get_config_for_profile(profile)
+        .await
+        .ok()
+        .and_then(|config| config.endpoint);
+
+    let mut client = get_momento_client(previous, endpoint.clone()).await?;
+    let generated: GeneratedApiToken = interact_with_momento(
+        "generating replacement api token...",
+        client.generate_api_token(expiry),
+    )
+    .await?
+    .into();
+    let mut new_client = get_momento_client(generated.api_token.clone(), endpoint).await?;
+    interact_with_momento("verifying the new token...", new_client.list_caches(None))
+        .await
+        .map_err(|e| CliError {
+            msg: format!(
+                "the new token did not work, so profile {profile} was not changed: {}",
+                e.msg
+            ),
+        })?;
+
+    save_generated_token(profile, &generated).await?;
+    console_info!(
+        "Rotated the token of profile {profile}. The old token is kept as previous_token and stays valid until it expires."
+    );
+    Ok(())
+}
+
+/// Decode an API key without calling Momento. `token` is a literal key, `-` to read the
+/// key from stdin, or `None` for the key of `profile`.
+pub async fn inspect_api_key(token: Option<String>, profile: &str) -> Result<(), CliError> {
+    let token: SecretString = match token.as_deref() {
+        Some("-") => {
+            let mut token = String::new();
+            tokio::io::stdin().read_to_string(&mut token).await?;
+            token.into()
         }
+        Some(token) => token.into(),
+        None => get_creds_for_profile(profile).await?.token,
     };
-    let mut client = get_momento_client(auth_token, endpoint).await?;
-    print_whatever_this_is_as_json(
-        &interact_with_momento("generating api token...", client.generate_api_token(expiry))
-            .await?,
+    let info = decode_api_key(token.expose_secret())?;
+    // There is no client to create, but the profile still picks the output format.
+    if let Ok(config) = get_config_for_profile(profile).await {
+        init_client_settings(&config);
+    }
+    if client_settings().output == OutputFormat::Json {
+        print_whatever_this_is_as_json(&info);
+        return Ok(());
+    }
+
+    let or_not_set = |value: Option<String>| value.unwrap_or_else(|| "<not set>".to_string());
+    console_data!("format: {}", info.format);
+    console_data!("endpoint: {}", or_not_set(info.endpoint.clone()));
+    console_data!(
+        "issued at: {}",
+        or_not_set(info.issued_at.map(|t| t.to_string()))
     );
+    console_data!(
+        "expires at: {}",
+        info.expires_at
+            .map(|t| t.to_string())
+            .unwrap_or_else(|| "never".to_string())
+    );
+    // The scopes are an encoded protobuf message that this CLI cannot decode yet, so
+    // they are printed as they appear in the key.
+    match &info.permissions {
+        Some(serde_json::Value::String(permissions)) => {
+            console_data!("permissions (encoded): {permissions}")
+        }
+        Some(permissions) => console_data!("permissions (encoded): {permissions}"),
+        None => console_data!("permissions: <not set>"),
+    }
+    if let Some(warning) = info.expiry_warning(Utc::now()) {
+        console_info!("Warning: {warning}");
+    }
     Ok(())
 }
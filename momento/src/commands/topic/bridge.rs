@@ -0,0 +1,330 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures::StreamExt;
+use momento::preview::topics::{SubscriptionItem, TopicClient, ValueKind};
+use momento::MomentoError;
+use tokio::task::JoinSet;
+use tokio::time::Instant;
+
+use crate::commands::topic::parse_interval;
+use crate::error::CliError;
+use crate::utils::console::console_info;
+
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+const PUBLISH_ATTEMPTS: u32 = 3;
+/// How long a bidirectional bridge waits for a payload it published to come back on the
+/// subscription to the same side.
+const ECHO_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// One side of a bridge: the client for a profile and the cache used as the topic namespace.
+pub struct BridgeEnd {
+    pub client: TopicClient,
+    pub cache_name: String,
+}
+
+#[derive(Default)]
+struct BridgeCounters {
+    received: AtomicU64,
+    forwarded: AtomicU64,
+    skipped: AtomicU64,
+    failed: AtomicU64,
+    discontinuities: AtomicU64,
+}
+
+impl BridgeCounters {
+    fn print(&self, forwarded_per_second: Option<f64>) {
+        let rate = match forwarded_per_second {
+            Some(rate) => format!(" ({rate:.1}/s)"),
+            None => "".to_string(),
+        };
+        console_info!(
+            "received={} forwarded={}{rate} skipped={} failed={} discontinuities={}",
+            self.received.load(Ordering::Relaxed),
+            self.forwarded.load(Ordering::Relaxed),
+            self.skipped.load(Ordering::Relaxed),
+            self.failed.load(Ordering::Relaxed),
+            self.discontinuities.load(Ordering::Relaxed)
+        );
+    }
+}
+
+/// Identifies a payload on a topic without keeping a copy of it.
+fn fingerprint(topic: &str, value: &ValueKind) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    topic.hash(&mut hasher);
+    match value {
+        ValueKind::Text(text) => (0u8, text.as_bytes()).hash(&mut hasher),
+        ValueKind::Binary(binary) => (1u8, binary.as_slice()).hash(&mut hasher),
+    }
+    hasher.finish()
+}
+
+/// The payloads a bidirectional bridge has published to one side and not yet seen come back
+/// on its subscription to that side. Their echoes are skipped, so that payloads can be
+/// republished unchanged without looping between the two sides.
+#[derive(Default)]
+struct PendingEchoes {
+    published: Mutex<HashMap<u64, VecDeque<Instant>>>,
+}
+
+impl PendingEchoes {
+    /// Remember a payload that is about to be published. It is recorded before publishing
+    /// because the echo can arrive before the publish call returns.
+    fn record(&self, fingerprint: u64, now: Instant) {
+        let mut published = self.published.lock().expect("lock poisoned");
+        // Echoes that never arrived, for example after a discontinuity, are forgotten.
+        published.retain(|_, sent_at| {
+            sent_at.retain(|sent_at| now.duration_since(*sent_at) < ECHO_TIMEOUT);
+            !sent_at.is_empty()
+        });
+        published.entry(fingerprint).or_default().push_back(now);
+    }
+
+    /// Whether the payload is the echo of one that was published, forgetting it if so.
+    fn take(&self, fingerprint: u64, now: Instant) -> bool {
+        let mut published = self.published.lock().expect("lock poisoned");
+        let Some(sent_at) = published.get_mut(&fingerprint) else {
+            return false;
+        };
+        while let Some(oldest) = sent_at.pop_front() {
+            if now.duration_since(oldest) < ECHO_TIMEOUT {
+                if sent_at.is_empty() {
+                    published.remove(&fingerprint);
+                }
+                return true;
+            }
+        }
+        published.remove(&fingerprint);
+        false
+    }
+}
+
+/// One side of a running bridge, with the payloads the bridge published to it.
+struct Side {
+    end: BridgeEnd,
+    echoes: PendingEchoes,
+}
+
+async fn publish_with_retries(
+    to: &BridgeEnd,
+    topic: &str,
+    value: &ValueKind,
+) -> Result<(), MomentoError> {
+    let mut backoff = INITIAL_BACKOFF;
+    let mut attempt = 1;
+    loop {
+        let result = match value {
+            ValueKind::Text(text) => {
+                to.client
+                    .publish(to.cache_name.clone(), topic.to_string(), text.clone())
+                    .await
+            }
+            ValueKind::Binary(binary) => {
+                to.client
+                    .publish(to.cache_name.clone(), topic.to_string(), binary.clone())
+                    .await
+            }
+        };
+        match result {
+            Err(e) if attempt < PUBLISH_ATTEMPTS => {
+                log::debug!("failed to publish to {topic}, retrying in {backoff:?}: {e:?}");
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Republish the messages of `topic` from `from` to `to`. With `bidirectional`, the payloads
+/// are recorded as pending echoes on `to`, and pending echoes received from `from` are skipped.
+async fn bridge_topic(
+    from: Arc<Side>,
+    to: Arc<Side>,
+    topic: String,
+    bidirectional: bool,
+    counters: Arc<BridgeCounters>,
+) {
+    let mut resume_at = None;
+    let mut backoff = INITIAL_BACKOFF;
+    loop {
+        let mut subscription = match from
+            .end
+            .client
+            .subscribe(from.end.cache_name.clone(), topic.clone(), resume_at)
+            .await
+        {
+            Ok(subscription) => {
+                backoff = INITIAL_BACKOFF;
+                subscription
+            }
+            Err(e) => {
+                console_info!("failed to subscribe to {topic}, retrying in {backoff:?}: {e:?}");
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+                continue;
+            }
+        };
+        log::debug!("subscribed to {topic} in cache {}", from.end.cache_name);
+
+        while let Some(item) = subscription.next().await {
+            let value = match item {
+                SubscriptionItem::Value(value) => value,
+                SubscriptionItem::Discontinuity(discontinuity) => {
+                    log::debug!("{topic}: {discontinuity:?}");
+                    resume_at = Some(discontinuity.new_sequence_number);
+                    counters.discontinuities.fetch_add(1, Ordering::Relaxed);
+                    continue;
+                }
+            };
+            resume_at = Some(value.topic_sequence_number);
+            counters.received.fetch_add(1, Ordering::Relaxed);
+            let value = value.kind;
+            let fingerprint = fingerprint(&topic, &value);
+            if bidirectional {
+                if from.echoes.take(fingerprint, Instant::now()) {
+                    counters.skipped.fetch_add(1, Ordering::Relaxed);
+                    continue;
+                }
+                to.echoes.record(fingerprint, Instant::now());
+            }
+            match publish_with_retries(&to.end, &topic, &value).await {
+                Ok(()) => counters.forwarded.fetch_add(1, Ordering::Relaxed),
+                Err(e) => {
+                    if bidirectional {
+                        to.echoes.take(fingerprint, Instant::now());
+                    }
+                    console_info!("dropped a message on {topic}: {e:?}");
+                    counters.failed.fetch_add(1, Ordering::Relaxed)
+                }
+            };
+        }
+        console_info!("the subscription to {topic} ended, resubscribing");
+    }
+}
+
+/// Subscribe to `topics` on `from` and republish every message unchanged to the same topic
+/// on `to`, and with `bidirectional` from `to` to `from` as well, until interrupted. Prints
+/// throughput counters every `stats_interval`.
+pub async fn run_bridge(
+    from: BridgeEnd,
+    to: BridgeEnd,
+    topics: Vec<String>,
+    bidirectional: bool,
+    stats_interval: String,
+) -> Result<(), CliError> {
+    let stats_interval = parse_interval("stats-interval", &stats_interval)?;
+    console_info!(
+        "Bridging {} from cache {} {} cache {}. Press Ctrl-C to stop.",
+        topics.join(", "),
+        from.cache_name,
+        if bidirectional { "to and from" } else { "to" },
+        to.cache_name
+    );
+
+    let side = |end| {
+        Arc::new(Side {
+            end,
+            echoes: PendingEchoes::default(),
+        })
+    };
+    let from = side(from);
+    let to = side(to);
+    let counters = Arc::new(BridgeCounters::default());
+    let mut bridges = JoinSet::new();
+    for topic in topics {
+        bridges.spawn(bridge_topic(
+            from.clone(),
+            to.clone(),
+            topic.clone(),
+            bidirectional,
+            counters.clone(),
+        ));
+        if bidirectional {
+            bridges.spawn(bridge_topic(
+                to.clone(),
+                from.clone(),
+                topic,
+                bidirectional,
+                counters.clone(),
+            ));
+        }
+    }
+
+    let mut stats = tokio::time::interval_at(Instant::now() + stats_interval, stats_interval);
+    let mut last_forwarded = 0;
+    loop {
+        tokio::select! {
+            _ = stats.tick() => {
+                let forwarded = counters.forwarded.load(Ordering::Relaxed);
+                let rate = (forwarded - last_forwarded) as f64 / stats_interval.as_secs_f64();
+                counters.print(Some(rate));
+                last_forwarded = forwarded;
+            }
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+
+    bridges.abort_all();
+    console_info!("Bridge stopped");
+    counters.print(None);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use momento::preview::topics::ValueKind;
+    use tokio::time::Instant;
+
+    use crate::commands::topic::bridge::{fingerprint, PendingEchoes, ECHO_TIMEOUT};
+
+    #[test]
+    fn fingerprint_tells_topics_and_kinds_apart() {
+        let text = |text: &str| ValueKind::Text(text.to_string());
+        assert_eq!(
+            fingerprint("a", &text("hello")),
+            fingerprint("a", &text("hello"))
+        );
+        assert_ne!(
+            fingerprint("a", &text("hello")),
+            fingerprint("b", &text("hello"))
+        );
+        assert_ne!(
+            fingerprint("a", &text("hello")),
+            fingerprint("a", &ValueKind::Binary(b"hello".to_vec()))
+        );
+    }
+
+    #[test]
+    fn pending_echoes_are_skipped_once_each() {
+        let echoes = PendingEchoes::default();
+        let now = Instant::now();
+        echoes.record(1, now);
+        echoes.record(1, now);
+        assert!(!echoes.take(2, now));
+        assert!(echoes.take(1, now));
+        assert!(echoes.take(1, now));
+        assert!(!echoes.take(1, now));
+    }
+
+    #[test]
+    fn pending_echoes_expire() {
+        let echoes = PendingEchoes::default();
+        let now = Instant::now();
+        echoes.record(1, now);
+        assert!(!echoes.take(1, now + ECHO_TIMEOUT + Duration::from_secs(1)));
+
+        echoes.record(2, now);
+        echoes.record(3, now + ECHO_TIMEOUT);
+        assert!(echoes.published.lock().expect("d'oh").get(&2).is_none());
+    }
+}
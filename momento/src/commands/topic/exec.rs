@@ -0,0 +1,314 @@
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use futures::{Stream, StreamExt};
+use momento::preview::topics::{SubscriptionItem, ValueKind};
+use momento_cli_opts::ExecErrorMode;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+use crate::commands::topic::filter::MessageFilter;
+use crate::error::CliError;
+use crate::utils::console::console_info;
+use crate::utils::process::shell_command;
+
+/// Run `command` once per message received on the subscription.
+///
+/// Each handler gets the message payload on stdin and the message metadata in
+/// `MOMENTO_TOPIC_*` environment variables. Messages removed by `filter` do not
+/// start a handler. At most `max_concurrency` handlers run at the same time;
+/// further messages wait for a free slot.
+#[allow(clippy::too_many_arguments)]
+pub async fn exec_subscription(
+    mut subscription: impl Stream<Item = SubscriptionItem> + Unpin,
+    filter: &MessageFilter,
+    cache_name: String,
+    topic: String,
+    command: String,
+    max_concurrency: u32,
+    on_error: ExecErrorMode,
+) -> Result<(), CliError> {
+    let permits = Arc::new(Semaphore::new(max_concurrency as usize));
+    let mut handlers: JoinSet<Result<(), CliError>> = JoinSet::new();
+    let mut failure: Option<CliError> = None;
+    // Set by a failing handler before it releases its permit, so that a message waiting for
+    // that permit does not start a handler after the failure in stop mode.
+    let handler_failed = Arc::new(AtomicBool::new(false));
+
+    loop {
+        tokio::select! {
+            // Reap finished handlers as we go so that failures are noticed promptly.
+            Some(joined) = handlers.join_next(), if !handlers.is_empty() => {
+                if let Err(e) = flatten_join_result(joined) {
+                    match on_error {
+                        ExecErrorMode::Stop => {
+                            failure = Some(e);
+                            break;
+                        }
+                        ExecErrorMode::Continue => console_info!("{}", e),
+                    }
+                }
+            }
+            item = subscription.next() => {
+                let value = match item {
+                    Some(SubscriptionItem::Value(value)) => value,
+                    Some(SubscriptionItem::Discontinuity(discontinuity)) => {
+                        log::debug!("{discontinuity:?}");
+                        continue;
+                    }
+                    None => break,
+                };
+                let payload = match filter.apply(value.kind) {
+                    Some(payload) => payload,
+                    None => continue,
+                };
+                let permit = permits
+                    .clone()
+                    .acquire_owned()
+                    .await
+                    .expect("the handler semaphore is never closed");
+                if on_error == ExecErrorMode::Stop && handler_failed.load(Ordering::SeqCst) {
+                    break;
+                }
+                let handler = build_handler_command(
+                    &command,
+                    &cache_name,
+                    &topic,
+                    value.topic_sequence_number,
+                    &payload,
+                );
+                let handler_failed = handler_failed.clone();
+                handlers.spawn(async move {
+                    let result = run_handler(handler, payload).await;
+                    if result.is_err() {
+                        handler_failed.store(true, Ordering::SeqCst);
+                    }
+                    drop(permit);
+                    result
+                });
+            }
+        }
+    }
+
+    // Let in-flight handlers finish instead of killing them halfway through.
+    while let Some(joined) = handlers.join_next().await {
+        if let Err(e) = flatten_join_result(joined) {
+            if on_error == ExecErrorMode::Stop && failure.is_none() {
+                failure = Some(e);
+            } else {
+                console_info!("{}", e);
+            }
+        }
+    }
+
+    match failure {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+fn flatten_join_result(
+    joined: Result<Result<(), CliError>, tokio::task::JoinError>,
+) -> Result<(), CliError> {
+    joined.map_err(|e| CliError {
+        msg: format!("exec handler task failed: {e}"),
+    })?
+}
+
+fn build_handler_command(
+    command: &str,
+    cache_name: &str,
+    topic: &str,
+    topic_sequence_number: u64,
+    payload: &ValueKind,
+) -> Command {
+    let mut handler = shell_command(command);
+    let kind = match payload {
+        ValueKind::Text(_) => "text",
+        ValueKind::Binary(_) => "binary",
+    };
+    handler
+        .env("MOMENTO_TOPIC_CACHE", cache_name)
+        .env("MOMENTO_TOPIC_NAME", topic)
+        .env(
+            "MOMENTO_TOPIC_SEQUENCE_NUMBER",
+            topic_sequence_number.to_string(),
+        )
+        .env("MOMENTO_TOPIC_VALUE_KIND", kind)
+        .stdin(Stdio::piped());
+    handler
+}
+
+async fn run_handler(mut handler: Command, payload: ValueKind) -> Result<(), CliError> {
+    let mut child = handler.spawn().map_err(|e| CliError {
+        msg: format!("failed to start exec handler: {e}"),
+    })?;
+    let bytes = match payload {
+        ValueKind::Text(text) => text.into_bytes(),
+        ValueKind::Binary(binary) => binary,
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        // A handler that does not read its stdin closes the pipe early; that is not a failure.
+        if let Err(e) = stdin.write_all(&bytes).await {
+            log::debug!("exec handler did not consume the whole payload: {e}");
+        }
+    }
+    let status = child.wait().await.map_err(|e| CliError {
+        msg: format!("failed to wait for exec handler: {e}"),
+    })?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(CliError {
+            msg: format!("exec handler failed with {status}"),
+        })
+    }
+}
+
+#[cfg(all(test, not(target_os = "windows")))]
+mod tests {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use futures::stream;
+    use momento::preview::topics::{SubscriptionItem, SubscriptionValue, ValueKind};
+    use momento_cli_opts::ExecErrorMode;
+
+    use crate::commands::topic::exec::{build_handler_command, exec_subscription};
+    use crate::commands::topic::filter::MessageFilter;
+
+    fn messages(payloads: &[&str]) -> impl futures::Stream<Item = SubscriptionItem> + Unpin {
+        let items: Vec<SubscriptionItem> = payloads
+            .iter()
+            .enumerate()
+            .map(|(i, payload)| {
+                SubscriptionItem::Value(SubscriptionValue {
+                    kind: ValueKind::Text(payload.to_string()),
+                    topic_sequence_number: i as u64 + 1,
+                })
+            })
+            .collect();
+        stream::iter(items)
+    }
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "momento-cli-test-exec-{name}-{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("d'oh");
+        dir
+    }
+
+    fn lines(path: &Path) -> Vec<String> {
+        let mut lines: Vec<String> = fs::read_to_string(path)
+            .unwrap_or_default()
+            .lines()
+            .map(str::to_string)
+            .collect();
+        lines.sort();
+        lines
+    }
+
+    #[tokio::test]
+    async fn handler_gets_metadata_in_env_and_payload_on_stdin() {
+        let dir = test_dir("env");
+        let out = dir.join("out");
+        let command = format!(
+            "echo \"$MOMENTO_TOPIC_CACHE $MOMENTO_TOPIC_NAME $MOMENTO_TOPIC_SEQUENCE_NUMBER $MOMENTO_TOPIC_VALUE_KIND $(cat)\" > {}",
+            out.display()
+        );
+        let mut handler = build_handler_command(
+            &command,
+            "cache",
+            "topic",
+            7,
+            &ValueKind::Binary(b"ignored".to_vec()),
+        );
+        let mut child = handler.spawn().expect("d'oh");
+        drop(child.stdin.take());
+        assert!(child.wait().await.expect("d'oh").success());
+        assert_eq!(vec!["cache topic 7 binary ".to_string()], lines(&out));
+
+        exec_subscription(
+            messages(&["hello"]),
+            &MessageFilter::default(),
+            "cache".to_string(),
+            "topic".to_string(),
+            command,
+            1,
+            ExecErrorMode::Stop,
+        )
+        .await
+        .expect("d'oh");
+        assert_eq!(vec!["cache topic 1 text hello".to_string()], lines(&out));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn handlers_never_exceed_max_concurrency() {
+        let dir = test_dir("concurrency");
+        let lock = dir.join("lock");
+        // mkdir fails if another handler holds the lock, which fails the whole run in stop mode.
+        let command = format!(
+            "mkdir {lock} && sleep 0.05 && rmdir {lock}",
+            lock = lock.display()
+        );
+        exec_subscription(
+            messages(&["a", "b", "c", "d"]),
+            &MessageFilter::default(),
+            "cache".to_string(),
+            "topic".to_string(),
+            command,
+            1,
+            ExecErrorMode::Stop,
+        )
+        .await
+        .expect("d'oh");
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn stop_mode_starts_no_handler_after_a_failure() {
+        let dir = test_dir("stop");
+        let out = dir.join("out");
+        let command = format!(
+            "read payload; echo $payload >> {}; [ $payload != fail ]",
+            out.display()
+        );
+        let result = exec_subscription(
+            messages(&["ok", "fail", "after", "after"]),
+            &MessageFilter::default(),
+            "cache".to_string(),
+            "topic".to_string(),
+            command.clone(),
+            1,
+            ExecErrorMode::Stop,
+        )
+        .await;
+        assert!(result.is_err());
+        assert_eq!(vec!["fail".to_string(), "ok".to_string()], lines(&out));
+
+        fs::remove_file(&out).expect("d'oh");
+        exec_subscription(
+            messages(&["ok", "fail", "after"]),
+            &MessageFilter::default(),
+            "cache".to_string(),
+            "topic".to_string(),
+            command,
+            1,
+            ExecErrorMode::Continue,
+        )
+        .await
+        .expect("d'oh");
+        assert_eq!(
+            vec!["after".to_string(), "fail".to_string(), "ok".to_string()],
+            lines(&out)
+        );
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,407 @@
+use std::cmp::Ordering;
+
+use momento::preview::topics::ValueKind;
+use regex::Regex;
+use serde_json::{Map, Value};
+
+use crate::error::CliError;
+
+/// A step in a jq-style path such as `.order.items[0].sku`.
+#[derive(Debug, PartialEq)]
+enum PathSegment {
+    Field(String),
+    Index(usize),
+}
+
+#[derive(Debug, PartialEq)]
+struct JsonPath {
+    source: String,
+    segments: Vec<PathSegment>,
+}
+
+impl JsonPath {
+    fn parse(path: &str) -> Result<Self, CliError> {
+        let (path, rest) = Self::parse_prefix(path.trim())?;
+        if !rest.trim().is_empty() {
+            return Err(CliError {
+                msg: format!("invalid path {}: unexpected {}", path.source, rest.trim()),
+            });
+        }
+        Ok(path)
+    }
+
+    /// Parse the path at the start of `input`, returning it and the rest of `input`. The
+    /// path ends at whitespace or an operator character; keys containing those can be
+    /// quoted in brackets, e.g. `.["a=b"]`.
+    fn parse_prefix(input: &str) -> Result<(Self, &str), CliError> {
+        let invalid = |reason: &str| CliError {
+            msg: format!("invalid path {input}: {reason}"),
+        };
+        let mut rest = input
+            .strip_prefix('.')
+            .ok_or_else(|| invalid("paths must start with '.'"))?;
+        let mut segments = vec![];
+        loop {
+            if let Some(bracketed) = rest.strip_prefix('[') {
+                let (segment, after) = parse_bracketed_segment(bracketed).map_err(invalid)?;
+                segments.push(segment);
+                rest = after.strip_prefix('.').unwrap_or(after);
+                continue;
+            }
+            let (field, after) = rest.split_at(
+                rest.find(|c: char| c == '.' || c == '[' || ends_path(c))
+                    .unwrap_or(rest.len()),
+            );
+            if !field.is_empty() {
+                segments.push(PathSegment::Field(field.to_string()));
+            }
+            rest = after;
+            match after.strip_prefix('.') {
+                Some(_) if field.is_empty() => return Err(invalid("empty field name")),
+                Some(after) => rest = after,
+                None if after.starts_with('[') => {}
+                None => break,
+            }
+        }
+        let path = JsonPath {
+            source: input[..input.len() - rest.len()].to_string(),
+            segments,
+        };
+        Ok((path, rest))
+    }
+
+    fn select<'a>(&self, value: &'a Value) -> Option<&'a Value> {
+        self.segments
+            .iter()
+            .try_fold(value, |current, segment| match segment {
+                PathSegment::Field(name) => current.get(name),
+                PathSegment::Index(index) => current.get(index),
+            })
+    }
+
+    /// The key used for this path in projected output: the path without its leading dot.
+    fn key(&self) -> &str {
+        &self.source[1..]
+    }
+}
+
+fn ends_path(c: char) -> bool {
+    c.is_whitespace() || matches!(c, '=' | '!' | '<' | '>')
+}
+
+/// Parse the inside of `[...]`, either an array index or a quoted key, returning the
+/// segment and the input after the closing `]`.
+fn parse_bracketed_segment(input: &str) -> Result<(PathSegment, &str), &'static str> {
+    let input = input.trim_start();
+    if input.starts_with('"') {
+        // The key may contain `]`, so the JSON string is read before looking for it.
+        let mut keys = serde_json::Deserializer::from_str(input).into_iter::<String>();
+        let key = match keys.next() {
+            Some(Ok(key)) => key,
+            _ => return Err("invalid quoted key"),
+        };
+        let after = input[keys.byte_offset()..]
+            .trim_start()
+            .strip_prefix(']')
+            .ok_or("expected ']' after a quoted key")?;
+        return Ok((PathSegment::Field(key), after));
+    }
+    let (index, after) = input.split_once(']').ok_or("missing ']'")?;
+    let index = index
+        .trim()
+        .parse::<usize>()
+        .map_err(|_| "array indexes must be non-negative integers")?;
+    Ok((PathSegment::Index(index), after))
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, PartialEq)]
+struct JsonPredicate {
+    path: JsonPath,
+    comparison: Option<(Comparison, Value)>,
+}
+
+impl JsonPredicate {
+    /// Parse `PATH [OPERATOR LITERAL]`, where the literal is JSON, e.g. `.count >= 3`
+    /// or `.type == "order.failed"`. A bare path matches when the value is present and
+    /// is neither `null` nor `false`.
+    fn parse(predicate: &str) -> Result<Self, CliError> {
+        // Longer operators first so that `<=` is not read as `<`.
+        const OPERATORS: [(&str, Comparison); 6] = [
+            ("==", Comparison::Eq),
+            ("!=", Comparison::Ne),
+            ("<=", Comparison::Le),
+            (">=", Comparison::Ge),
+            ("<", Comparison::Lt),
+            (">", Comparison::Gt),
+        ];
+        // The path comes first, so that operator characters in quoted keys are not
+        // mistaken for the operator.
+        let (path, rest) = JsonPath::parse_prefix(predicate.trim())?;
+        let rest = rest.trim_start();
+        if rest.is_empty() {
+            return Ok(JsonPredicate {
+                path,
+                comparison: None,
+            });
+        }
+        let (token, comparison) = OPERATORS
+            .iter()
+            .find(|(token, _)| rest.starts_with(token))
+            .ok_or_else(|| CliError {
+                msg: format!("invalid filter {predicate}: expected an operator before {rest}"),
+            })?;
+        let literal = rest[token.len()..].trim();
+        let literal = serde_json::from_str(literal).map_err(|e| CliError {
+            msg: format!(
+                "invalid filter {predicate}: {literal} is not a JSON value (strings must be quoted): {e}"
+            ),
+        })?;
+        Ok(JsonPredicate {
+            path,
+            comparison: Some((*comparison, literal)),
+        })
+    }
+
+    fn matches(&self, document: &Value) -> bool {
+        let selected = self.path.select(document);
+        match &self.comparison {
+            None => !matches!(
+                selected,
+                None | Some(Value::Null) | Some(Value::Bool(false))
+            ),
+            Some((comparison, literal)) => {
+                let ordering = selected.and_then(|value| compare(value, literal));
+                match comparison {
+                    Comparison::Eq => ordering == Some(Ordering::Equal),
+                    Comparison::Ne => ordering != Some(Ordering::Equal),
+                    Comparison::Lt => ordering == Some(Ordering::Less),
+                    Comparison::Le => {
+                        matches!(ordering, Some(Ordering::Less | Ordering::Equal))
+                    }
+                    Comparison::Gt => ordering == Some(Ordering::Greater),
+                    Comparison::Ge => {
+                        matches!(ordering, Some(Ordering::Greater | Ordering::Equal))
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Numbers compare numerically and strings lexically; other values are only ever
+/// equal or unequal.
+fn compare(value: &Value, literal: &Value) -> Option<Ordering> {
+    match (value, literal) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64()?.partial_cmp(&b.as_f64()?),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        (a, b) if a == b => Some(Ordering::Equal),
+        _ => None,
+    }
+}
+
+/// Client-side filtering and projection of topic messages, applied before they are
+/// printed or handed to an `--exec` handler.
+#[derive(Debug, Default)]
+pub struct MessageFilter {
+    regex: Option<(Regex, regex::bytes::Regex)>,
+    predicates: Vec<JsonPredicate>,
+    projection: Vec<JsonPath>,
+}
+
+impl MessageFilter {
+    pub fn new(
+        regex: Option<String>,
+        predicates: Vec<String>,
+        projection: Vec<String>,
+    ) -> Result<Self, CliError> {
+        let regex = match regex {
+            Some(pattern) => {
+                let invalid = |e: regex::Error| CliError {
+                    msg: format!("invalid filter regex {pattern}: {e}"),
+                };
+                Some((
+                    Regex::new(&pattern).map_err(invalid)?,
+                    regex::bytes::Regex::new(&pattern).map_err(invalid)?,
+                ))
+            }
+            None => None,
+        };
+        Ok(MessageFilter {
+            regex,
+            predicates: predicates
+                .iter()
+                .map(|p| JsonPredicate::parse(p))
+                .collect::<Result<_, _>>()?,
+            projection: projection
+                .iter()
+                .map(|p| JsonPath::parse(p))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    /// Returns the value to output, or `None` if the message is filtered out.
+    pub fn apply(&self, value: ValueKind) -> Option<ValueKind> {
+        if let Some((text_regex, bytes_regex)) = &self.regex {
+            let matched = match &value {
+                ValueKind::Text(text) => text_regex.is_match(text),
+                ValueKind::Binary(binary) => bytes_regex.is_match(binary),
+            };
+            if !matched {
+                return None;
+            }
+        }
+        if self.predicates.is_empty() && self.projection.is_empty() {
+            return Some(value);
+        }
+
+        // Binary payloads are often JSON too, so try them as well.
+        let document: Value = match &value {
+            ValueKind::Text(text) => serde_json::from_str(text),
+            ValueKind::Binary(binary) => serde_json::from_slice(binary),
+        }
+        .ok()?;
+        if !self.predicates.iter().all(|p| p.matches(&document)) {
+            return None;
+        }
+        if self.projection.is_empty() {
+            return Some(value);
+        }
+        let projected: Map<String, Value> = self
+            .projection
+            .iter()
+            .map(|path| {
+                (
+                    path.key().to_string(),
+                    path.select(&document).cloned().unwrap_or(Value::Null),
+                )
+            })
+            .collect();
+        Some(ValueKind::Text(Value::Object(projected).to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use momento::preview::topics::ValueKind;
+
+    use crate::commands::topic::filter::MessageFilter;
+
+    fn text(value: &str) -> ValueKind {
+        ValueKind::Text(value.to_string())
+    }
+
+    fn output(filter: &MessageFilter, value: ValueKind) -> Option<String> {
+        match filter.apply(value) {
+            Some(ValueKind::Text(text)) => Some(text),
+            Some(ValueKind::Binary(binary)) => Some(String::from_utf8(binary).expect("d'oh")),
+            None => None,
+        }
+    }
+
+    #[test]
+    fn regex_filters_text_and_binary() {
+        let filter = MessageFilter::new(Some("fail".to_string()), vec![], vec![]).expect("d'oh");
+        assert_eq!(
+            Some("it failed".to_string()),
+            output(&filter, text("it failed"))
+        );
+        assert_eq!(None, output(&filter, text("it worked")));
+        assert_eq!(
+            Some("binary fail".to_string()),
+            output(&filter, ValueKind::Binary(b"binary fail".to_vec()))
+        );
+    }
+
+    #[test]
+    fn predicates_compare_json_fields() {
+        let filter = MessageFilter::new(
+            None,
+            vec![
+                r#".type == "order.failed""#.to_string(),
+                ".order.items[0].qty >= 2".to_string(),
+            ],
+            vec![],
+        )
+        .expect("d'oh");
+        let failed = r#"{"type":"order.failed","order":{"items":[{"qty":2}]}}"#;
+        assert_eq!(Some(failed.to_string()), output(&filter, text(failed)));
+        assert_eq!(
+            None,
+            output(
+                &filter,
+                text(r#"{"type":"order.failed","order":{"items":[{"qty":1}]}}"#)
+            )
+        );
+        assert_eq!(
+            None,
+            output(&filter, text(r#"{"type":"order.placed","order":{}}"#))
+        );
+        assert_eq!(None, output(&filter, text("not json")));
+    }
+
+    #[test]
+    fn bare_path_predicate_checks_truthiness() {
+        let filter = MessageFilter::new(None, vec![".urgent".to_string()], vec![]).expect("d'oh");
+        assert!(filter.apply(text(r#"{"urgent":true}"#)).is_some());
+        assert!(filter.apply(text(r#"{"urgent":false}"#)).is_none());
+        assert!(filter.apply(text(r#"{"other":1}"#)).is_none());
+    }
+
+    #[test]
+    fn projection_selects_fields() {
+        let filter = MessageFilter::new(
+            None,
+            vec![],
+            vec![
+                ".type".to_string(),
+                ".order.id".to_string(),
+                ".missing".to_string(),
+            ],
+        )
+        .expect("d'oh");
+        assert_eq!(
+            Some(r#"{"missing":null,"order.id":7,"type":"order.failed"}"#.to_string()),
+            output(
+                &filter,
+                ValueKind::Binary(br#"{"type":"order.failed","order":{"id":7}}"#.to_vec())
+            )
+        );
+    }
+
+    #[test]
+    fn quoted_keys_may_contain_operators() {
+        let filter = MessageFilter::new(
+            None,
+            vec![r#".["a=b"] == 1"#.to_string(), r#".c["x > y"]"#.to_string()],
+            vec![r#".["a=b"]"#.to_string()],
+        )
+        .expect("d'oh");
+        assert_eq!(
+            Some(r#"{"[\"a=b\"]":1}"#.to_string()),
+            output(&filter, text(r#"{"a=b":1,"c":{"x > y":true}}"#))
+        );
+        assert_eq!(
+            None,
+            output(&filter, text(r#"{"a=b":2,"c":{"x > y":true}}"#))
+        );
+    }
+
+    #[test]
+    fn invalid_filters_are_rejected() {
+        assert!(MessageFilter::new(None, vec!["type == 1".to_string()], vec![]).is_err());
+        assert!(MessageFilter::new(None, vec![".type == failed".to_string()], vec![]).is_err());
+        assert!(MessageFilter::new(None, vec![".type ~ 1".to_string()], vec![]).is_err());
+        assert!(MessageFilter::new(None, vec![], vec![".a b".to_string()]).is_err());
+        assert!(MessageFilter::new(Some("(".to_string()), vec![], vec![]).is_err());
+    }
+}
@@ -1,17 +1,36 @@
+use std::time::Duration;
+
 use futures::StreamExt;
 use momento::{preview::topics::Subscription, MomentoResult};
 
+use crate::error::CliError;
 use crate::utils::console::console_data;
 
-pub async fn print_subscription(mut subscription: Subscription) -> MomentoResult<()> {
+use self::filter::MessageFilter;
+
+pub mod bridge;
+pub mod exec;
+pub mod filter;
+pub mod ping;
+pub mod record;
+
+pub async fn print_subscription(
+    mut subscription: Subscription,
+    filter: &MessageFilter,
+) -> MomentoResult<()> {
     while let Some(item) = subscription.next().await {
         match item {
-            momento::preview::topics::SubscriptionItem::Value(value) => match value.kind {
-                momento::preview::topics::ValueKind::Text(text) => console_data!("{text}"),
-                momento::preview::topics::ValueKind::Binary(binary) => {
-                    console_data!("{{\"kind\": \"binary\", \"length\": {}}}", binary.len())
+            momento::preview::topics::SubscriptionItem::Value(value) => {
+                match filter.apply(value.kind) {
+                    Some(momento::preview::topics::ValueKind::Text(text)) => {
+                        console_data!("{text}")
+                    }
+                    Some(momento::preview::topics::ValueKind::Binary(binary)) => {
+                        console_data!("{{\"kind\": \"binary\", \"length\": {}}}", binary.len())
+                    }
+                    None => {}
                 }
-            },
+            }
             momento::preview::topics::SubscriptionItem::Discontinuity(discontinuity) => {
                 console_data!("{discontinuity:?}")
             }
@@ -19,3 +38,24 @@ pub async fn print_subscription(mut subscription: Subscription) -> MomentoResult
     }
     Ok(())
 }
+
+/// Parse a human readable duration option such as `10s` or `1m`.
+pub(crate) fn parse_duration(name: &str, value: &str) -> Result<Duration, CliError> {
+    value
+        .parse::<humantime::Duration>()
+        .map(Into::into)
+        .map_err(|e| CliError {
+            msg: format!("failed to parse {name} duration {value}: {e}"),
+        })
+}
+
+/// Like `parse_duration`, for durations used as a timer period, which must not be zero.
+pub(crate) fn parse_interval(name: &str, value: &str) -> Result<Duration, CliError> {
+    let interval = parse_duration(name, value)?;
+    if interval.is_zero() {
+        return Err(CliError {
+            msg: format!("{name} must be greater than 0"),
+        });
+    }
+    Ok(interval)
+}
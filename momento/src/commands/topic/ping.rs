@@ -0,0 +1,303 @@
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use futures::StreamExt;
+use momento::preview::topics::{SubscriptionItem, TopicClient, ValueKind};
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+use crate::commands::topic::{parse_duration, parse_interval};
+use crate::error::CliError;
+use crate::utils::client::print_whatever_this_is_as_json;
+use crate::utils::console::{console_data, console_info};
+
+/// The payload of a probe message.
+#[derive(Serialize, Deserialize)]
+struct Probe {
+    /// Identifies this run so that probes from other `topic ping` runs are ignored.
+    probe: String,
+    seq: u64,
+    /// Microseconds between the start of the run and the publish call.
+    sent_us: u64,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct LatencySummary {
+    pub min_ms: f64,
+    pub p50_ms: f64,
+    pub p90_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+    pub mean_ms: f64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct PingReport {
+    pub sent: u64,
+    pub publish_errors: u64,
+    pub received: u64,
+    pub lost: u64,
+    pub loss_percent: f64,
+    pub reordered: u64,
+    pub duplicates: u64,
+    pub latency: Option<LatencySummary>,
+}
+
+#[derive(Default)]
+struct PingStats {
+    seen: HashSet<u64>,
+    highest_seq: Option<u64>,
+    latencies_us: Vec<u64>,
+    reordered: u64,
+    duplicates: u64,
+}
+
+impl PingStats {
+    fn observe(&mut self, seq: u64, latency_us: u64) {
+        if !self.seen.insert(seq) {
+            self.duplicates += 1;
+            return;
+        }
+        match self.highest_seq {
+            Some(highest) if seq < highest => self.reordered += 1,
+            _ => self.highest_seq = Some(seq),
+        }
+        self.latencies_us.push(latency_us);
+    }
+
+    fn received(&self) -> u64 {
+        self.seen.len() as u64
+    }
+
+    fn report(&self, sent: u64, publish_errors: u64) -> PingReport {
+        let published = sent - publish_errors;
+        let lost = published.saturating_sub(self.received());
+        PingReport {
+            sent,
+            publish_errors,
+            received: self.received(),
+            lost,
+            loss_percent: if published == 0 {
+                0.0
+            } else {
+                lost as f64 * 100.0 / published as f64
+            },
+            reordered: self.reordered,
+            duplicates: self.duplicates,
+            latency: summarize_latencies(&self.latencies_us),
+        }
+    }
+}
+
+fn summarize_latencies(latencies_us: &[u64]) -> Option<LatencySummary> {
+    if latencies_us.is_empty() {
+        return None;
+    }
+    let mut sorted = latencies_us.to_vec();
+    sorted.sort_unstable();
+    let to_ms = |us: u64| us as f64 / 1000.0;
+    let total: u64 = sorted.iter().sum();
+    Some(LatencySummary {
+        min_ms: to_ms(sorted[0]),
+        p50_ms: to_ms(percentile(&sorted, 50.0)),
+        p90_ms: to_ms(percentile(&sorted, 90.0)),
+        p99_ms: to_ms(percentile(&sorted, 99.0)),
+        max_ms: to_ms(sorted[sorted.len() - 1]),
+        mean_ms: to_ms(total / sorted.len() as u64),
+    })
+}
+
+/// Nearest-rank percentile of an already sorted, non-empty slice.
+fn percentile(sorted: &[u64], percentile: f64) -> u64 {
+    let rank = (percentile / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+fn print_summary(report: &PingReport) {
+    let latency = match &report.latency {
+        Some(l) => format!(
+            "min={:.2}ms p50={:.2}ms p90={:.2}ms p99={:.2}ms max={:.2}ms",
+            l.min_ms, l.p50_ms, l.p90_ms, l.p99_ms, l.max_ms
+        ),
+        None => "no probes received yet".to_string(),
+    };
+    console_data!(
+        "sent={} received={} lost={} ({:.1}%) reordered={} duplicates={} {latency}",
+        report.sent,
+        report.received,
+        report.lost,
+        report.loss_percent,
+        report.reordered,
+        report.duplicates
+    );
+}
+
+/// Publish probes to `topic` at a fixed rate through `client` while subscribed
+/// to the same topic, and report latency, loss and reordering.
+#[allow(clippy::too_many_arguments)]
+pub async fn ping_topic(
+    client: TopicClient,
+    cache_name: String,
+    topic: String,
+    rate: f64,
+    count: Option<u64>,
+    summary_interval: String,
+    timeout: String,
+    json: bool,
+) -> Result<(), CliError> {
+    // tokio's interval panics on a zero period, so it is kept to at least 1ms.
+    let period = Duration::try_from_secs_f64(1.0 / rate)
+        .map_err(|_| CliError {
+            msg: format!("rate must be greater than 0, got {rate}"),
+        })?
+        .max(Duration::from_millis(1));
+    let summary_interval = parse_interval("summary-interval", &summary_interval)?;
+    let timeout = parse_duration("timeout", &timeout)?;
+
+    let client = Arc::new(client);
+    let mut subscription = client
+        .subscribe(cache_name.clone(), topic.clone(), None)
+        .await?;
+
+    let probe_id = format!(
+        "{}-{}",
+        std::process::id(),
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos()
+    );
+    let started = Instant::now();
+    let sent = Arc::new(AtomicU64::new(0));
+    let publish_errors = Arc::new(AtomicU64::new(0));
+    let stop = Arc::new(AtomicBool::new(false));
+
+    let mut publisher = {
+        let client = client.clone();
+        let probe_id = probe_id.clone();
+        let sent = sent.clone();
+        let publish_errors = publish_errors.clone();
+        let stop = stop.clone();
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(period);
+            let mut seq = 0;
+            while !stop.load(Ordering::Relaxed) && count.is_none_or(|c| seq < c) {
+                ticks.tick().await;
+                let probe = Probe {
+                    probe: probe_id.clone(),
+                    seq,
+                    sent_us: started.elapsed().as_micros() as u64,
+                };
+                let payload = serde_json::to_string(&probe).expect("probes always serialize");
+                sent.fetch_add(1, Ordering::Relaxed);
+                if let Err(e) = client
+                    .publish(cache_name.clone(), topic.clone(), payload)
+                    .await
+                {
+                    log::debug!("failed to publish probe {seq}: {e:?}");
+                    publish_errors.fetch_add(1, Ordering::Relaxed);
+                }
+                seq += 1;
+            }
+        })
+    };
+
+    let mut stats = PingStats::default();
+    let mut summaries = tokio::time::interval_at(started + summary_interval, summary_interval);
+    let mut drain_deadline: Option<Instant> = None;
+    let current_report = |stats: &PingStats| {
+        stats.report(
+            sent.load(Ordering::Relaxed),
+            publish_errors.load(Ordering::Relaxed),
+        )
+    };
+
+    loop {
+        // Far enough away that it never fires before publishing finishes.
+        let deadline = drain_deadline.unwrap_or_else(|| Instant::now() + Duration::from_secs(3600));
+        tokio::select! {
+            item = subscription.next() => {
+                let text = match item {
+                    Some(SubscriptionItem::Value(value)) => match value.kind {
+                        ValueKind::Text(text) => text,
+                        ValueKind::Binary(_) => continue,
+                    },
+                    Some(SubscriptionItem::Discontinuity(discontinuity)) => {
+                        log::debug!("{discontinuity:?}");
+                        continue;
+                    }
+                    None => break,
+                };
+                let received_us = started.elapsed().as_micros() as u64;
+                match serde_json::from_str::<Probe>(&text) {
+                    Ok(probe) if probe.probe == probe_id => {
+                        stats.observe(probe.seq, received_us.saturating_sub(probe.sent_us))
+                    }
+                    _ => log::debug!("ignoring a message that is not one of our probes"),
+                }
+                // Once publishing is done there is nothing left to wait for after the last probe.
+                if drain_deadline.is_some() && current_report(&stats).lost == 0 {
+                    break;
+                }
+            }
+            _ = summaries.tick(), if !json => print_summary(&current_report(&stats)),
+            _ = tokio::signal::ctrl_c(), if !stop.load(Ordering::Relaxed) => {
+                stop.store(true, Ordering::Relaxed);
+            }
+            _ = &mut publisher, if drain_deadline.is_none() => {
+                if current_report(&stats).lost == 0 {
+                    break;
+                }
+                drain_deadline = Some(Instant::now() + timeout);
+            }
+            _ = tokio::time::sleep_until(deadline), if drain_deadline.is_some() => break,
+        }
+    }
+
+    let report = current_report(&stats);
+    if json {
+        print_whatever_this_is_as_json(&report);
+    } else {
+        console_info!("Probing finished");
+        print_summary(&report);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::topic::ping::{percentile, summarize_latencies, PingStats};
+
+    #[test]
+    fn percentile_uses_nearest_rank() {
+        let sorted: Vec<u64> = (1..=100).collect();
+        assert_eq!(50, percentile(&sorted, 50.0));
+        assert_eq!(99, percentile(&sorted, 99.0));
+        assert_eq!(100, percentile(&sorted, 100.0));
+        assert_eq!(7, percentile(&[7], 50.0));
+    }
+
+    #[test]
+    fn stats_count_loss_reordering_and_duplicates() {
+        let mut stats = PingStats::default();
+        stats.observe(0, 1000);
+        stats.observe(2, 3000);
+        stats.observe(1, 2000);
+        stats.observe(2, 3000);
+
+        let report = stats.report(5, 1);
+        assert_eq!(3, report.received);
+        assert_eq!(1, report.lost);
+        assert_eq!(25.0, report.loss_percent);
+        assert_eq!(1, report.reordered);
+        assert_eq!(1, report.duplicates);
+        assert_eq!(summarize_latencies(&[1000, 2000, 3000]), report.latency,);
+    }
+
+    #[test]
+    fn no_latency_summary_without_probes() {
+        assert!(summarize_latencies(&[]).is_none());
+    }
+}
@@ -1,15 +1,152 @@
 use serde::{Deserialize, Serialize};
 
+use crate::{error::CliError, utils::secret::SecretString};
+
 pub const ENV_VAR_NAME_MOMENTO_CONFIG_DIR: &str = "MOMENTO_CONFIG_DIR";
+pub const ENV_VAR_NAME_MOMENTO_API_KEY: &str = "MOMENTO_API_KEY";
+pub const ENV_VAR_NAME_MOMENTO_AUTH_TOKEN: &str = "MOMENTO_AUTH_TOKEN";
+pub const ENV_VAR_NAME_MOMENTO_PROFILE: &str = "MOMENTO_PROFILE";
+pub const ENV_VAR_NAME_MOMENTO_CACHE: &str = "MOMENTO_CACHE";
+pub const ENV_VAR_NAME_MOMENTO_TTL: &str = "MOMENTO_TTL";
+pub const ENV_VAR_NAME_MOMENTO_ENDPOINT: &str = "MOMENTO_ENDPOINT";
+pub const ENV_VAR_NAME_MOMENTO_CREDENTIAL_PROCESS_CACHE: &str = "MOMENTO_CREDENTIAL_PROCESS_CACHE";
+pub const ENV_VAR_NAME_MOMENTO_CREDENTIALS_UNLOCK_SECONDS: &str =
+    "MOMENTO_CREDENTIALS_UNLOCK_SECONDS";
 pub const DEFAULT_CACHE_NAME: &str = "default-cache";
 
 #[derive(Deserialize, Serialize, Clone, Default)]
 pub struct Config {
     pub cache: String,
     pub ttl: u64,
+    /// Used when `--endpoint` is not passed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub endpoint: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<OutputFormat>,
+    /// How long to wait for each cache request before giving up.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_timeout_seconds: Option<u64>,
+}
+
+/// The `Config` fields that `momento config get/set/unset` can change.
+pub const CONFIG_KEYS: [&str; 5] = [
+    "cache",
+    "ttl",
+    "endpoint",
+    "output",
+    "request_timeout_seconds",
+];
+/// The settings every profile needs, which can be changed but not unset.
+pub const REQUIRED_CONFIG_KEYS: [&str; 2] = ["cache", "ttl"];
+
+pub fn ensure_config_key(key: &str) -> Result<(), CliError> {
+    if CONFIG_KEYS.contains(&key) {
+        Ok(())
+    } else {
+        Err(CliError {
+            msg: format!(
+                "unknown setting {key}, expected one of {}",
+                CONFIG_KEYS.join(", ")
+            ),
+        })
+    }
+}
+
+pub fn parse_config_number(key: &str, value: &str) -> Result<u64, CliError> {
+    let number = value.parse::<u64>().map_err(|e| CliError {
+        msg: format!("could not parse {key} as a u64: {e:?}"),
+    })?;
+    // A timeout of 0 would fail every request.
+    if key == "request_timeout_seconds" && number == 0 {
+        return Err(CliError {
+            msg: format!("{key} must be greater than 0"),
+        });
+    }
+    Ok(number)
+}
+
+/// Check that `value` can be stored as the `key` setting of a profile.
+pub fn validate_config_value(key: &str, value: &str) -> Result<(), CliError> {
+    ensure_config_key(key)?;
+    match key {
+        "ttl" | "request_timeout_seconds" => parse_config_number(key, value).map(|_| ()),
+        "output" => value.parse::<OutputFormat>().map(|_| ()),
+        _ if value.is_empty() => Err(CliError {
+            msg: format!("{key} cannot be empty"),
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// How commands print the data they return.
+#[derive(Deserialize, Serialize, Clone, Copy, Default, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = CliError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(CliError {
+                msg: format!("invalid output format {s}, expected text or json"),
+            }),
+        }
+    }
+}
+
+impl std::fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OutputFormat::Text => write!(f, "text"),
+            OutputFormat::Json => write!(f, "json"),
+        }
+    }
 }
 
 #[derive(Deserialize, Serialize, Clone, Default, Debug)]
 pub struct Credentials {
-    pub token: String,
+    pub token: SecretString,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{validate_config_value, Config, OutputFormat, CONFIG_KEYS};
+
+    #[test]
+    fn config_keys_match_config_fields() {
+        let config = Config {
+            cache: "cache".to_string(),
+            ttl: 600,
+            endpoint: Some("endpoint".to_string()),
+            output: Some(OutputFormat::Json),
+            request_timeout_seconds: Some(15),
+        };
+        let fields: Vec<String> = match serde_json::to_value(config).expect("d'oh") {
+            serde_json::Value::Object(fields) => fields.keys().cloned().collect(),
+            other => panic!("expected an object, got {other}"),
+        };
+        let mut keys: Vec<String> = CONFIG_KEYS.iter().map(|k| k.to_string()).collect();
+        keys.sort();
+        assert_eq!(keys, fields);
+    }
+
+    #[test]
+    fn validate_config_value_checks_types() {
+        assert!(validate_config_value("ttl", "90").is_ok());
+        assert!(validate_config_value("ttl", "ninety").is_err());
+        assert!(validate_config_value("request_timeout_seconds", "-1").is_err());
+        assert!(validate_config_value("request_timeout_seconds", "0").is_err());
+        assert!(validate_config_value("request_timeout_seconds", "1").is_ok());
+        assert!(validate_config_value("output", "json").is_ok());
+        assert!(validate_config_value("output", "yaml").is_err());
+        assert!(validate_config_value("cache", "").is_err());
+        assert!(validate_config_value("token", "secret").is_err());
+    }
 }
@@ -1,16 +1,25 @@
-use std::{panic, process::exit};
+use std::{io::Write, panic, process::exit};
 
 use clap::Parser;
-use commands::topic::print_subscription;
+use commands::configure::configure_cli::{
+    configure_momento, resolve_token_flags, ConfigureOverrides,
+};
+use commands::topic::{
+    bridge::BridgeEnd, exec::exec_subscription, filter::MessageFilter, print_subscription,
+};
 use env_logger::Env;
 use error::CliError;
 use log::{debug, error, LevelFilter};
-use momento::{CredentialProviderBuilder, MomentoError};
+use momento::MomentoError;
 use momento_cli_opts::PreviewCommand;
-use utils::{console::output_info, user::get_creds_and_config};
+use utils::{
+    client::get_topic_client,
+    console::output_info,
+    secret::mask_secrets,
+    user::{get_creds_and_config, resolve_profile_name},
+};
 
 use crate::utils::console::console_info;
-use crate::utils::user::clobber_session_token;
 
 mod commands;
 mod config;
@@ -18,6 +27,7 @@ mod error;
 mod utils;
 
 async fn run_momento_command(args: momento_cli_opts::Momento) -> Result<(), CliError> {
+    let profile = resolve_profile_name(args.profile).await;
     match args.command {
         momento_cli_opts::Subcommand::Cache {
             endpoint,
@@ -32,7 +42,8 @@ async fn run_momento_command(args: momento_cli_opts::Momento) -> Result<(), CliE
                     .or(cache_name_flag)
                     .or(cache_name_flag_for_backward_compatibility)
                     .expect("The argument group guarantees 1 or the other");
-                let (creds, _config) = get_creds_and_config(&args.profile).await?;
+                let (creds, config) = get_creds_and_config(&profile).await?;
+                let endpoint = endpoint.or(config.endpoint);
                 commands::cache::cache_cli::create_cache(cache_name.clone(), creds.token, endpoint)
                     .await?;
                 debug!("created cache {cache_name}")
@@ -42,7 +53,8 @@ async fn run_momento_command(args: momento_cli_opts::Momento) -> Result<(), CliE
                 cache_name_flag,
                 cache_name_flag_for_backward_compatibility,
             } => {
-                let (creds, _config) = get_creds_and_config(&args.profile).await?;
+                let (creds, config) = get_creds_and_config(&profile).await?;
+                let endpoint = endpoint.or(config.endpoint);
                 let cache_name = cache_name
                     .or(cache_name_flag)
                     .or(cache_name_flag_for_backward_compatibility)
@@ -52,14 +64,16 @@ async fn run_momento_command(args: momento_cli_opts::Momento) -> Result<(), CliE
                 debug!("deleted cache {}", cache_name)
             }
             momento_cli_opts::CacheCommand::List {} => {
-                let (creds, _config) = get_creds_and_config(&args.profile).await?;
+                let (creds, config) = get_creds_and_config(&profile).await?;
+                let endpoint = endpoint.or(config.endpoint);
                 commands::cache::cache_cli::list_caches(creds.token, endpoint).await?
             }
             momento_cli_opts::CacheCommand::Flush {
                 cache_name,
                 cache_name_flag,
             } => {
-                let (creds, _config) = get_creds_and_config(&args.profile).await?;
+                let (creds, config) = get_creds_and_config(&profile).await?;
+                let endpoint = endpoint.or(config.endpoint);
                 let cache_name = cache_name
                     .or(cache_name_flag)
                     .expect("The argument group guarantees 1 or the other");
@@ -74,7 +88,8 @@ async fn run_momento_command(args: momento_cli_opts::Momento) -> Result<(), CliE
                 value_flag,
                 ttl_seconds,
             } => {
-                let (creds, config) = get_creds_and_config(&args.profile).await?;
+                let (creds, config) = get_creds_and_config(&profile).await?;
+                let endpoint = endpoint.or(config.endpoint);
                 let cache_name = cache_name
                     .or(cache_name_flag_for_backward_compatibility)
                     .unwrap_or(config.cache);
@@ -100,7 +115,8 @@ async fn run_momento_command(args: momento_cli_opts::Momento) -> Result<(), CliE
                 key,
                 key_flag,
             } => {
-                let (creds, config) = get_creds_and_config(&args.profile).await?;
+                let (creds, config) = get_creds_and_config(&profile).await?;
+                let endpoint = endpoint.or(config.endpoint);
                 let key = key
                     .or(key_flag)
                     .expect("The argument group guarantees 1 or the other");
@@ -120,7 +136,8 @@ async fn run_momento_command(args: momento_cli_opts::Momento) -> Result<(), CliE
                 key,
                 key_flag,
             } => {
-                let (creds, config) = get_creds_and_config(&args.profile).await?;
+                let (creds, config) = get_creds_and_config(&profile).await?;
+                let endpoint = endpoint.or(config.endpoint);
                 let key = key
                     .or(key_flag)
                     .expect("The argument group guarantees 1 or the other");
@@ -138,61 +155,277 @@ async fn run_momento_command(args: momento_cli_opts::Momento) -> Result<(), CliE
         momento_cli_opts::Subcommand::Topic {
             endpoint,
             operation,
-        } => {
-            let (creds, config) = get_creds_and_config(&args.profile).await?;
-            let mut credential_provider_builder =
-                CredentialProviderBuilder::from_string(creds.token);
-            if let Some(endpoint_override) = endpoint {
-                credential_provider_builder =
-                    credential_provider_builder.with_momento_endpoint(endpoint_override)
-            }
-            let credential_provider = credential_provider_builder.build()?;
-
-            let mut client =
-                momento::preview::topics::TopicClient::connect(credential_provider, Some("cli"))
-                    .map_err(Into::<CliError>::into)?;
-            match operation {
-                momento_cli_opts::TopicCommand::Publish {
+        } => match operation {
+            momento_cli_opts::TopicCommand::Ping {
+                cache_name,
+                topic,
+                rate,
+                count,
+                summary_interval,
+                timeout,
+                json,
+            } => {
+                let (creds, config) = get_creds_and_config(&profile).await?;
+                let endpoint = endpoint.or(config.endpoint);
+                let client = get_topic_client(creds.token, endpoint).await?;
+                let cache_name = cache_name.unwrap_or(config.cache);
+                commands::topic::ping::ping_topic(
+                    client,
                     cache_name,
                     topic,
-                    value,
-                } => {
-                    let cache_name = cache_name.unwrap_or(config.cache);
-                    client
-                        .publish_mut(cache_name, topic, value)
-                        .await
-                        .map_err(Into::<CliError>::into)?;
+                    rate,
+                    count,
+                    summary_interval,
+                    timeout,
+                    json,
+                )
+                .await?;
+            }
+            momento_cli_opts::TopicCommand::Publish {
+                cache_name,
+                topic,
+                value,
+            } => {
+                let (creds, config) = get_creds_and_config(&profile).await?;
+                let endpoint = endpoint.or(config.endpoint);
+                let mut client = get_topic_client(creds.token, endpoint).await?;
+                let cache_name = cache_name.unwrap_or(config.cache);
+                client
+                    .publish_mut(cache_name, topic, value)
+                    .await
+                    .map_err(Into::<CliError>::into)?;
+            }
+            momento_cli_opts::TopicCommand::Subscribe {
+                cache_name,
+                topic,
+                filter_regex,
+                filters,
+                projection,
+                exec,
+                max_concurrency,
+                on_error,
+            } => {
+                let filter = MessageFilter::new(filter_regex, filters, projection)?;
+                let (creds, config) = get_creds_and_config(&profile).await?;
+                let endpoint = endpoint.or(config.endpoint);
+                let client = get_topic_client(creds.token, endpoint).await?;
+                let cache_name = cache_name.unwrap_or(config.cache);
+                let subscription = client
+                    .subscribe(cache_name.clone(), topic.clone(), None)
+                    .await
+                    .map_err(|e| CliError {
+                        msg: format!("the subscription ended without receiving any values: {e:?}"),
+                    })?;
+                if let Some(command) = exec {
+                    exec_subscription(
+                        subscription,
+                        &filter,
+                        cache_name,
+                        topic,
+                        command,
+                        max_concurrency,
+                        on_error,
+                    )
+                    .await?;
+                    console_info!("The subscription ended");
+                    return Ok(());
                 }
-                momento_cli_opts::TopicCommand::Subscribe { cache_name, topic } => {
-                    let cache_name = cache_name.unwrap_or(config.cache);
-                    let subscription =
-                        client
-                            .subscribe(cache_name, topic, None)
-                            .await
-                            .map_err(|e| CliError {
-                                msg: format!(
-                                    "the subscription ended without receiving any values: {e:?}"
-                                ),
-                            })?;
-                    match print_subscription(subscription).await {
-                        Ok(_) => console_info!("The subscription ended"),
-                        Err(e) => match e {
-                            momento::MomentoError::Interrupted {
-                                description,
-                                source,
-                            } => {
-                                output_info(&format!("The subscription ended: {description}"));
-                                console_info!("detail: {source:?}");
-                            }
-                            _ => return Err(e.into()),
-                        },
-                    }
+                match print_subscription(subscription, &filter).await {
+                    Ok(_) => console_info!("The subscription ended"),
+                    Err(e) => match e {
+                        momento::MomentoError::Interrupted {
+                            description,
+                            source,
+                        } => {
+                            output_info(&format!("The subscription ended: {description}"));
+                            console_info!("detail: {source:?}");
+                        }
+                        _ => return Err(e.into()),
+                    },
                 }
             }
+            momento_cli_opts::TopicCommand::Record {
+                cache_name,
+                topic,
+                out,
+            } => {
+                let (creds, config) = get_creds_and_config(&profile).await?;
+                let endpoint = endpoint.or(config.endpoint);
+                let client = get_topic_client(creds.token, endpoint).await?;
+                let cache_name = cache_name.unwrap_or(config.cache);
+                let subscription = client
+                    .subscribe(cache_name, topic, None)
+                    .await
+                    .map_err(Into::<CliError>::into)?;
+                commands::topic::record::record_subscription(subscription, &out).await?;
+            }
+            momento_cli_opts::TopicCommand::Replay {
+                cache_name,
+                topic,
+                input,
+                speed,
+            } => {
+                let (creds, config) = get_creds_and_config(&profile).await?;
+                let endpoint = endpoint.or(config.endpoint);
+                let mut client = get_topic_client(creds.token, endpoint).await?;
+                let cache_name = cache_name.unwrap_or(config.cache);
+                commands::topic::record::replay_recording(
+                    &mut client,
+                    cache_name,
+                    topic,
+                    &input,
+                    speed,
+                )
+                .await?;
+            }
+            momento_cli_opts::TopicCommand::Bridge {
+                from_profile,
+                from_cache,
+                to_profile,
+                to_cache,
+                topics,
+                bidirectional,
+                from_endpoint,
+                to_endpoint,
+                stats_interval,
+            } => {
+                if endpoint.is_some() {
+                    return Err(CliError {
+                        msg: "the two sides of a bridge can have different endpoints, use --from-endpoint and --to-endpoint instead of --endpoint".to_string(),
+                    });
+                }
+                let (from_creds, from_config) = get_creds_and_config(&from_profile).await?;
+                let (to_creds, to_config) = get_creds_and_config(&to_profile).await?;
+                commands::topic::bridge::run_bridge(
+                    BridgeEnd {
+                        client: get_topic_client(
+                            from_creds.token,
+                            from_endpoint.or(from_config.endpoint),
+                        )
+                        .await?,
+                        cache_name: from_cache.unwrap_or(from_config.cache),
+                    },
+                    BridgeEnd {
+                        client: get_topic_client(
+                            to_creds.token,
+                            to_endpoint.or(to_config.endpoint),
+                        )
+                        .await?,
+                        cache_name: to_cache.unwrap_or(to_config.cache),
+                    },
+                    topics,
+                    bidirectional,
+                    stats_interval,
+                )
+                .await?;
+            }
+        },
+        momento_cli_opts::Subcommand::Configure {
+            quick,
+            token,
+            token_file,
+            token_env,
+            cache,
+            ttl,
+            endpoint,
+            no_create_cache,
+            no_validate,
+        } => {
+            let token =
+                resolve_token_flags(token, token_file, token_env, |name| std::env::var(name))
+                    .await?;
+            configure_momento(
+                quick,
+                &profile,
+                ConfigureOverrides {
+                    token,
+                    cache,
+                    ttl,
+                    endpoint,
+                    skip_cache_creation: no_create_cache,
+                    skip_token_validation: no_validate,
+                },
+            )
+            .await?
+        }
+        momento_cli_opts::Subcommand::Profile { operation } => match operation {
+            momento_cli_opts::ProfileCommand::List {} => {
+                commands::profile::profile_cli::list_profiles().await?
+            }
+            momento_cli_opts::ProfileCommand::Show { name } => {
+                commands::profile::profile_cli::show_profile(&name.unwrap_or(profile)).await?
+            }
+            momento_cli_opts::ProfileCommand::Delete { name, force } => {
+                commands::profile::profile_cli::delete_profile_everywhere(&name, force).await?
+            }
+            momento_cli_opts::ProfileCommand::Rename { from, to } => {
+                commands::profile::profile_cli::rename_profile_everywhere(&from, &to).await?
+            }
+            momento_cli_opts::ProfileCommand::Copy { from, to } => {
+                commands::profile::profile_cli::copy_profile_everywhere(&from, &to).await?
+            }
+            momento_cli_opts::ProfileCommand::Use { name } => {
+                commands::profile::profile_cli::use_profile(&name).await?
+            }
+        },
+        momento_cli_opts::Subcommand::Doctor { json } => {
+            commands::doctor::doctor_cli::run_doctor(&profile, json).await?
         }
-        momento_cli_opts::Subcommand::Configure { quick } => {
-            commands::configure::configure_cli::configure_momento(quick, &args.profile).await?
+        momento_cli_opts::Subcommand::Env {
+            format,
+            secret_name,
+        } => commands::env::env_cli::print_env(&profile, format, &secret_name).await?,
+        momento_cli_opts::Subcommand::Session { operation } => match operation {
+            momento_cli_opts::SessionCommand::Status {} => {
+                commands::session::session_cli::session_status(&profile).await?
+            }
+            momento_cli_opts::SessionCommand::Refresh { via } => {
+                commands::session::session_cli::start_session(&profile, via).await?
+            }
+        },
+        momento_cli_opts::Subcommand::Logout {} => {
+            commands::session::session_cli::logout(&profile).await?
         }
+        momento_cli_opts::Subcommand::Token { operation } => match operation {
+            momento_cli_opts::TokenCommand::Inspect { token } => {
+                commands::tokens::inspect_api_key(token, &profile).await?
+            }
+            momento_cli_opts::TokenCommand::Rotate {
+                valid_for,
+                never_expire,
+            } => commands::tokens::rotate_profile_token(&profile, never_expire, valid_for).await?,
+        },
+        momento_cli_opts::Subcommand::Credentials { operation } => match operation {
+            momento_cli_opts::CredentialsCommand::Encrypt {} => {
+                commands::credentials::credentials_cli::encrypt_credentials().await?
+            }
+            momento_cli_opts::CredentialsCommand::Decrypt {} => {
+                commands::credentials::credentials_cli::decrypt_credentials().await?
+            }
+            momento_cli_opts::CredentialsCommand::Unlock { seconds } => {
+                commands::credentials::credentials_cli::unlock_credentials(seconds).await?
+            }
+            momento_cli_opts::CredentialsCommand::Lock {} => {
+                commands::credentials::credentials_cli::lock_credentials().await?
+            }
+        },
+        momento_cli_opts::Subcommand::Config { operation } => match operation {
+            momento_cli_opts::ConfigCommand::Migrate { force } => {
+                commands::config::config_cli::migrate_config(force).await?
+            }
+            momento_cli_opts::ConfigCommand::MigrateDirs {} => {
+                commands::config::config_cli::migrate_config_dirs().await?
+            }
+            momento_cli_opts::ConfigCommand::Get { key } => {
+                commands::config::config_cli::get_config_value(&profile, &key).await?
+            }
+            momento_cli_opts::ConfigCommand::Set { key, value } => {
+                commands::config::config_cli::set_config_value(&profile, &key, &value).await?
+            }
+            momento_cli_opts::ConfigCommand::Unset { key } => {
+                commands::config::config_cli::unset_config_value(&profile, &key).await?
+            }
+        },
         momento_cli_opts::Subcommand::Account { operation } => match operation {
             // This command has been removed. It now just prints out an error message.
             momento_cli_opts::AccountCommand::Signup {
@@ -205,7 +438,8 @@ async fn run_momento_command(args: momento_cli_opts::Momento) -> Result<(), CliE
                 operation,
             } => match operation {
                 momento_cli_opts::SigningKeyCommand::Create { ttl_minutes } => {
-                    let (creds, _config) = get_creds_and_config(&args.profile).await?;
+                    let (creds, config) = get_creds_and_config(&profile).await?;
+                    let endpoint = endpoint.or(config.endpoint);
                     commands::signingkey::signingkey_cli::create_signing_key(
                         ttl_minutes,
                         creds.token,
@@ -214,7 +448,8 @@ async fn run_momento_command(args: momento_cli_opts::Momento) -> Result<(), CliE
                     .await?;
                 }
                 momento_cli_opts::SigningKeyCommand::Revoke { key_id } => {
-                    let (creds, _config) = get_creds_and_config(&args.profile).await?;
+                    let (creds, config) = get_creds_and_config(&profile).await?;
+                    let endpoint = endpoint.or(config.endpoint);
                     commands::signingkey::signingkey_cli::revoke_signing_key(
                         key_id.clone(),
                         creds.token,
@@ -224,42 +459,29 @@ async fn run_momento_command(args: momento_cli_opts::Momento) -> Result<(), CliE
                     debug!("revoked signing key {}", key_id)
                 }
                 momento_cli_opts::SigningKeyCommand::List {} => {
-                    let (creds, _config) = get_creds_and_config(&args.profile).await?;
+                    let (creds, config) = get_creds_and_config(&profile).await?;
+                    let endpoint = endpoint.or(config.endpoint);
                     commands::signingkey::signingkey_cli::list_signing_keys(creds.token, endpoint)
                         .await?
                 }
             },
             momento_cli_opts::PreviewCommand::Login { via } => {
-                match commands::login::login(via).await {
-                    Ok(credentials) => {
-                        let session_token = credentials.token();
-                        let session_duration = credentials.valid_for();
-                        debug!("{session_token}");
-                        clobber_session_token(
-                            Some(session_token.to_string()),
-                            session_duration.as_secs() as u32,
-                        )
-                        .await?;
-                        console_info!("Login valid for {}m", session_duration.as_secs() / 60);
-                    }
-                    Err(auth_error) => {
-                        return Err(CliError {
-                            msg: format!("auth error: {auth_error:?}"),
-                        })
-                    }
-                }
+                commands::session::session_cli::start_session(&profile, via).await?
             }
             momento_cli_opts::PreviewCommand::GenerateToken {
                 valid_for,
                 never_expire,
+                save_to_profile,
                 endpoint,
             } => {
-                let (creds, _config) = get_creds_and_config(&args.profile).await?;
+                let (creds, config) = get_creds_and_config(&profile).await?;
+                let endpoint = endpoint.or(config.endpoint);
                 commands::tokens::generate_api_token(
                     creds.token,
                     endpoint,
                     never_expire,
                     valid_for,
+                    save_to_profile,
                 )
                 .await?;
             }
@@ -297,7 +519,7 @@ async fn run_momento_command(args: momento_cli_opts::Momento) -> Result<(), CliE
 impl From<MomentoError> for CliError {
     fn from(val: MomentoError) -> Self {
         CliError {
-            msg: format!("{val:?}"),
+            msg: mask_secrets(&format!("{val:?}")),
         }
     }
 }
@@ -322,6 +544,17 @@ async fn main() {
             .default_filter_or(log_level)
             .default_write_style_or("always"),
     )
+    // Debug logs end up in support tickets, so keys are masked wherever they come from.
+    .format(|buf, record| {
+        writeln!(
+            buf,
+            "[{} {} {}] {}",
+            buf.timestamp(),
+            buf.default_styled_level(record.level()),
+            record.target(),
+            mask_secrets(&record.args().to_string())
+        )
+    })
     .init();
 
     if let Err(e) = run_momento_command(args).await {
@@ -0,0 +1,209 @@
+use base64::Engine;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::error::CliError;
+
+/// How long before a token expires `configure` starts warning about it.
+const EXPIRY_WARNING_DAYS: i64 = 7;
+
+/// The base64 JSON envelope of a v1 API key.
+#[derive(Deserialize)]
+struct V1ApiKey {
+    endpoint: String,
+    api_key: String,
+}
+
+/// The JWT claims the CLI knows about. Legacy tokens carry their endpoints in `c` and `cp`.
+#[derive(Deserialize)]
+struct Claims {
+    exp: Option<i64>,
+    iat: Option<i64>,
+    c: Option<String>,
+    cp: Option<String>,
+    p: Option<serde_json::Value>,
+}
+
+/// What can be learned about an API key without calling Momento.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct ApiKeyInfo {
+    pub format: String,
+    pub endpoint: Option<String>,
+    #[serde(serialize_with = "serialize_rfc3339")]
+    pub issued_at: Option<DateTime<Utc>>,
+    /// `None` for keys that never expire.
+    #[serde(serialize_with = "serialize_rfc3339")]
+    pub expires_at: Option<DateTime<Utc>>,
+    /// The permission scopes of the key, still encoded as they appear in the `p` claim.
+    pub permissions: Option<serde_json::Value>,
+}
+
+fn serialize_rfc3339<S: Serializer>(
+    time: &Option<DateTime<Utc>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    time.map(|time| time.to_rfc3339()).serialize(serializer)
+}
+
+fn invalid_api_key(reason: impl std::fmt::Display) -> CliError {
+    CliError {
+        msg: format!("not a Momento API key: {reason}"),
+    }
+}
+
+fn decode_jwt_claims(jwt: &str) -> Result<Claims, CliError> {
+    let payload = match jwt.split('.').collect::<Vec<_>>()[..] {
+        [_, payload, _] => payload,
+        _ => return Err(invalid_api_key("expected a JWT with 3 parts")),
+    };
+    let payload = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(payload.trim_end_matches('='))
+        .map_err(invalid_api_key)?;
+    serde_json::from_slice(&payload).map_err(invalid_api_key)
+}
+
+fn timestamp(seconds: Option<i64>) -> Option<DateTime<Utc>> {
+    seconds.and_then(|seconds| Utc.timestamp_opt(seconds, 0).single())
+}
+
+/// Decode a v1 API key or a legacy JWT auth token locally.
+pub fn decode_api_key(token: &str) -> Result<ApiKeyInfo, CliError> {
+    let token = token.trim();
+    let v1_key = base64::engine::general_purpose::STANDARD
+        .decode(token)
+        .ok()
+        .and_then(|decoded| serde_json::from_slice::<V1ApiKey>(&decoded).ok());
+    let (format, jwt, endpoint) = match v1_key {
+        Some(key) => ("v1", key.api_key, Some(key.endpoint)),
+        None => ("legacy jwt", token.to_string(), None),
+    };
+    let claims = decode_jwt_claims(&jwt)?;
+    let endpoint = endpoint.or_else(|| {
+        claims
+            .cp
+            .as_deref()
+            .and_then(|cp| cp.strip_prefix("control."))
+            .or_else(|| claims.c.as_deref().and_then(|c| c.strip_prefix("cache.")))
+            .map(|endpoint| endpoint.to_string())
+    });
+    Ok(ApiKeyInfo {
+        format: format.to_string(),
+        endpoint,
+        issued_at: timestamp(claims.iat),
+        expires_at: timestamp(claims.exp),
+        permissions: claims.p,
+    })
+}
+
+impl ApiKeyInfo {
+    /// A warning for keys that have expired or will expire soon.
+    pub fn expiry_warning(&self, now: DateTime<Utc>) -> Option<String> {
+        let expires_at = self.expires_at?;
+        if expires_at <= now {
+            Some(format!("this token expired at {expires_at}"))
+        } else if expires_at - now < Duration::days(EXPIRY_WARNING_DAYS) {
+            let hours = (expires_at - now).num_hours();
+            Some(format!(
+                "this token expires at {expires_at}, in {}",
+                if hours < 48 {
+                    format!("{hours} hours")
+                } else {
+                    format!("{} days", hours / 24)
+                }
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::Engine;
+    use chrono::{TimeZone, Utc};
+
+    use crate::utils::api_key::decode_api_key;
+
+    fn encode_jwt(claims: &str) -> String {
+        let engine = base64::engine::general_purpose::URL_SAFE_NO_PAD;
+        format!(
+            "{}.{}.signature",
+            engine.encode(r#"{"alg":"HS512"}"#),
+            engine.encode(claims)
+        )
+    }
+
+    #[test]
+    fn decode_v1_api_key() {
+        let jwt = encode_jwt(r#"{"sub":"someone","ver":1,"p":"CgQKAggB","iat":1700000000}"#);
+        let key = base64::engine::general_purpose::STANDARD.encode(format!(
+            r#"{{"endpoint":"cell-us-east-1-1.prod.a.momentohq.com","api_key":"{jwt}"}}"#
+        ));
+        let info = decode_api_key(&key).expect("d'oh");
+        assert_eq!("v1", info.format);
+        assert_eq!(
+            Some("cell-us-east-1-1.prod.a.momentohq.com".to_string()),
+            info.endpoint
+        );
+        assert_eq!(Utc.timestamp_opt(1700000000, 0).single(), info.issued_at);
+        assert_eq!(None, info.expires_at);
+        assert_eq!(Some(serde_json::json!("CgQKAggB")), info.permissions);
+    }
+
+    #[test]
+    fn decode_legacy_jwt() {
+        let jwt = encode_jwt(
+            r#"{"sub":"someone","c":"cache.cell-4-us-west-2-1.prod.a.momentohq.com","cp":"control.cell-4-us-west-2-1.prod.a.momentohq.com","exp":1700600000}"#,
+        );
+        let info = decode_api_key(&jwt).expect("d'oh");
+        assert_eq!("legacy jwt", info.format);
+        assert_eq!(
+            Some("cell-4-us-west-2-1.prod.a.momentohq.com".to_string()),
+            info.endpoint
+        );
+        assert_eq!(Utc.timestamp_opt(1700600000, 0).single(), info.expires_at);
+        assert_eq!(None, info.permissions);
+    }
+
+    #[test]
+    fn decode_keeps_permission_scopes() {
+        let scopes = "ChQKEgoGCgQKAggBEggKBnNjb3BlZA==";
+        let jwt = encode_jwt(&format!(
+            r#"{{"sub":"someone","ver":1,"p":"{scopes}","exp":1700600000}}"#
+        ));
+        let info = decode_api_key(&jwt).expect("d'oh");
+        assert_eq!(Some(serde_json::json!(scopes)), info.permissions);
+        assert_eq!(
+            Some(serde_json::json!(scopes)),
+            serde_json::to_value(&info)
+                .expect("d'oh")
+                .get("permissions")
+                .cloned()
+        );
+    }
+
+    #[test]
+    fn decode_rejects_other_strings() {
+        assert!(decode_api_key("awesome-token").is_err());
+        assert!(decode_api_key("a.b.c").is_err());
+    }
+
+    #[test]
+    fn expiry_warning_only_for_expired_or_expiring_tokens() {
+        let info = decode_api_key(&encode_jwt(r#"{"exp":1700000000}"#)).expect("d'oh");
+        let at = |seconds| Utc.timestamp_opt(seconds, 0).single().expect("d'oh");
+        assert_eq!(
+            Some("this token expired at 2023-11-14 22:13:20 UTC".to_string()),
+            info.expiry_warning(at(1700000000))
+        );
+        assert_eq!(
+            Some("this token expires at 2023-11-14 22:13:20 UTC, in 10 hours".to_string()),
+            info.expiry_warning(at(1700000000 - 10 * 3600))
+        );
+        assert_eq!(
+            Some("this token expires at 2023-11-14 22:13:20 UTC, in 3 days".to_string()),
+            info.expiry_warning(at(1700000000 - 3 * 86400))
+        );
+        assert_eq!(None, info.expiry_warning(at(1700000000 - 30 * 86400)));
+    }
+}
@@ -1,16 +1,47 @@
-use std::{future::Future, time::Duration};
+use std::{future::Future, sync::OnceLock, time::Duration};
 
 use momento::{
-    response::MomentoError, CredentialProviderBuilder, SimpleCacheClient, SimpleCacheClientBuilder,
+    preview::topics::TopicClient, response::MomentoError, CredentialProviderBuilder,
+    SimpleCacheClient, SimpleCacheClientBuilder,
 };
 
-use crate::{error::CliError, utils::console::console_data};
+use crate::{
+    config::{Config, OutputFormat},
+    error::CliError,
+    utils::{console::console_data, secret::SecretString},
+};
+
+const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Profile settings that apply to every client this process creates.
+#[derive(Default)]
+pub struct ClientSettings {
+    pub output: OutputFormat,
+    pub request_timeout: Option<Duration>,
+}
+
+static CLIENT_SETTINGS: OnceLock<ClientSettings> = OnceLock::new();
+
+/// Remember the output format and request timeout of the profile a command runs with.
+/// Only the first call has an effect, so commands that read a second profile (like
+/// `topic bridge`) keep the settings of the first one.
+pub fn init_client_settings(config: &Config) {
+    let _ = CLIENT_SETTINGS.set(ClientSettings {
+        output: config.output.unwrap_or_default(),
+        request_timeout: config.request_timeout_seconds.map(Duration::from_secs),
+    });
+}
+
+pub fn client_settings() -> &'static ClientSettings {
+    CLIENT_SETTINGS.get_or_init(ClientSettings::default)
+}
 
 pub async fn get_momento_client(
-    auth_token: String,
+    auth_token: SecretString,
     endpoint: Option<String>,
 ) -> Result<SimpleCacheClient, CliError> {
-    let mut credential_provider_builder = CredentialProviderBuilder::from_string(auth_token);
+    let mut credential_provider_builder =
+        CredentialProviderBuilder::from_string(auth_token.expose_secret().to_string());
     if let Some(momento_override) = endpoint {
         credential_provider_builder =
             credential_provider_builder.with_momento_endpoint(momento_override);
@@ -18,7 +49,9 @@ pub async fn get_momento_client(
     let credential_provider = credential_provider_builder.build()?;
     SimpleCacheClientBuilder::new_with_explicit_agent_name(
         credential_provider,
-        Duration::from_secs(120),
+        client_settings()
+            .request_timeout
+            .unwrap_or(DEFAULT_REQUEST_TIMEOUT),
         "cli",
     )
     .map_or_else(
@@ -27,6 +60,20 @@ pub async fn get_momento_client(
     )
 }
 
+pub async fn get_topic_client(
+    auth_token: SecretString,
+    endpoint: Option<String>,
+) -> Result<TopicClient, CliError> {
+    let mut credential_provider_builder =
+        CredentialProviderBuilder::from_string(auth_token.expose_secret().to_string());
+    if let Some(momento_override) = endpoint {
+        credential_provider_builder =
+            credential_provider_builder.with_momento_endpoint(momento_override);
+    }
+    let credential_provider = credential_provider_builder.build()?;
+    TopicClient::connect(credential_provider, Some("cli")).map_err(Into::<CliError>::into)
+}
+
 pub fn print_whatever_this_is_as_json<T>(value: &T)
 where
     T: serde::Serialize,
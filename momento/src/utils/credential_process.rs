@@ -0,0 +1,237 @@
+use std::collections::BTreeMap;
+use std::process::Stdio;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::ENV_VAR_NAME_MOMENTO_CREDENTIAL_PROCESS_CACHE,
+    error::CliError,
+    utils::{
+        file::{get_credential_process_cache_file_path, lock_config_dir, write_to_file},
+        process::shell_command,
+        secret::SecretString,
+    },
+};
+
+/// The JSON document a `credential_process` command prints on stdout, e.g.
+/// `{"token": "...", "expiry": "2024-06-01T12:00:00Z"}`.
+#[derive(Deserialize)]
+struct CredentialProcessOutput {
+    token: SecretString,
+    /// RFC 3339 timestamp after which the token can no longer be used.
+    #[serde(default)]
+    expiry: Option<String>,
+}
+
+/// A token from a `credential_process` that is reused until it expires.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+struct CachedToken {
+    command: String,
+    token: SecretString,
+    valid_until: i64,
+}
+
+fn parse_output(stdout: &[u8]) -> Result<(SecretString, Option<DateTime<Utc>>), CliError> {
+    let output: CredentialProcessOutput = serde_json::from_slice(stdout).map_err(|e| CliError {
+        msg: format!(
+            "credential_process output must be a JSON object with a \"token\" and an optional \"expiry\": {e}"
+        ),
+    })?;
+    let token = SecretString::new(output.token.expose_secret().trim());
+    if token.is_empty() {
+        return Err(CliError {
+            msg: "credential_process returned an empty token".to_string(),
+        });
+    }
+    let expiry = match output.expiry {
+        Some(expiry) => Some(
+            DateTime::parse_from_rfc3339(&expiry)
+                .map_err(|e| CliError {
+                    msg: format!("credential_process returned an invalid expiry {expiry}: {e}"),
+                })?
+                .with_timezone(&Utc),
+        ),
+        None => None,
+    };
+    Ok((token, expiry))
+}
+
+/// Like sessions, a token is not reused when it is about to expire.
+fn is_usable(valid_until: i64, now: DateTime<Utc>) -> bool {
+    (now + Duration::seconds(10)).timestamp() < valid_until
+}
+
+/// Tokens returned during this process. They are only written to the state dir when
+/// persisting them has been opted into.
+static MEMORY_CACHE: Mutex<BTreeMap<String, CachedToken>> = Mutex::new(BTreeMap::new());
+
+/// Whether the tokens are cached in the state dir, in plain text, for later commands.
+fn persist_cache() -> bool {
+    std::env::var(ENV_VAR_NAME_MOMENTO_CREDENTIAL_PROCESS_CACHE)
+        .is_ok_and(|value| matches!(value.as_str(), "1" | "true"))
+}
+
+async fn read_cache() -> BTreeMap<String, CachedToken> {
+    let path = match get_credential_process_cache_file_path() {
+        Ok(path) => path,
+        Err(_) => return BTreeMap::new(),
+    };
+    if !persist_cache() {
+        // Do not leave tokens cached before persisting them became opt-in lying around.
+        if tokio::fs::remove_file(&path).await.is_ok() {
+            log::debug!("Removed the credential_process cache, as persisting it is not enabled");
+        }
+        return BTreeMap::new();
+    }
+    tokio::fs::read(path)
+        .await
+        .ok()
+        .and_then(|contents| serde_json::from_slice(&contents).ok())
+        .unwrap_or_default()
+}
+
+async fn write_cache(cache: &BTreeMap<String, CachedToken>) -> Result<(), CliError> {
+    let path = get_credential_process_cache_file_path()?;
+    write_to_file(&path, serde_json::to_string_pretty(cache)?).await
+}
+
+async fn cache_token(profile: &str, cached: CachedToken) -> Result<(), CliError> {
+    MEMORY_CACHE
+        .lock()
+        .expect("lock poisoned")
+        .insert(profile.to_string(), cached.clone());
+    if !persist_cache() {
+        return Ok(());
+    }
+    let _lock = lock_config_dir().await?;
+    let mut cache = read_cache().await;
+    cache.insert(profile.to_string(), cached);
+    write_cache(&cache).await
+}
+
+async fn run_credential_process(
+    profile: &str,
+    command: &str,
+) -> Result<(SecretString, Option<DateTime<Utc>>), CliError> {
+    log::debug!("Running credential_process for profile {profile}");
+    // The command may need to ask for a passphrase or approval, so only stdout is captured.
+    let output = shell_command(command)
+        .stdin(Stdio::inherit())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .map_err(|e| CliError {
+            msg: format!("failed to run credential_process for profile {profile}: {e}"),
+        })?
+        .wait_with_output()
+        .await
+        .map_err(|e| CliError {
+            msg: format!("failed to run credential_process for profile {profile}: {e}"),
+        })?;
+    if !output.status.success() {
+        return Err(CliError {
+            msg: format!(
+                "credential_process for profile {profile} failed with {}",
+                output.status
+            ),
+        });
+    }
+    parse_output(&output.stdout).map_err(|e| CliError {
+        msg: format!("{} (profile {profile})", e.msg),
+    })
+}
+
+/// Get a token for `profile` from its `credential_process` command, reusing the last
+/// token the command returned until that token expires.
+pub async fn get_credential_process_token(
+    profile: &str,
+    command: &str,
+) -> Result<SecretString, CliError> {
+    let in_memory = MEMORY_CACHE
+        .lock()
+        .expect("lock poisoned")
+        .get(profile)
+        .cloned();
+    let cached = match in_memory {
+        Some(cached) => Some(cached),
+        None => read_cache().await.remove(profile),
+    };
+    if let Some(cached) = cached {
+        if cached.command == command && is_usable(cached.valid_until, Utc::now()) {
+            log::debug!("Using the cached credential_process token for profile {profile}");
+            return Ok(cached.token.clone());
+        }
+    }
+
+    let (token, expiry) = run_credential_process(profile, command).await?;
+    match expiry {
+        Some(expiry) if !is_usable(expiry.timestamp(), Utc::now()) => Err(CliError {
+            msg: format!(
+                "credential_process for profile {profile} returned a token that expired at {expiry}"
+            ),
+        }),
+        Some(expiry) => {
+            let cached = CachedToken {
+                command: command.to_string(),
+                token: token.clone(),
+                valid_until: expiry.timestamp(),
+            };
+            // A token that could not be cached is still good for this command.
+            if let Err(e) = cache_token(profile, cached).await {
+                log::debug!("failed to cache the credential_process token: {e:?}");
+            }
+            Ok(token)
+        }
+        None => Ok(token),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use crate::utils::credential_process::{is_usable, parse_output};
+
+    #[test]
+    fn parse_output_with_expiry() {
+        let (token, expiry) =
+            parse_output(br#"{"token": " secret-token\n", "expiry": "2024-06-01T12:00:00+02:00"}"#)
+                .expect("d'oh");
+        assert_eq!("secret-token", token.expose_secret());
+        assert_eq!(
+            Some(
+                Utc.with_ymd_and_hms(2024, 6, 1, 10, 0, 0)
+                    .single()
+                    .expect("d'oh")
+            ),
+            expiry
+        );
+    }
+
+    #[test]
+    fn parse_output_without_expiry() {
+        let (token, expiry) = parse_output(br#"{"token": "secret-token"}"#).expect("d'oh");
+        assert_eq!("secret-token", token.expose_secret());
+        assert_eq!(None, expiry);
+    }
+
+    #[test]
+    fn parse_output_rejects_bad_documents() {
+        assert!(parse_output(b"secret-token").is_err());
+        assert!(parse_output(br#"{"token": ""}"#).is_err());
+        assert!(parse_output(br#"{"token": "t", "expiry": "tomorrow"}"#).is_err());
+    }
+
+    #[test]
+    fn tokens_about_to_expire_are_not_usable() {
+        let now = Utc
+            .with_ymd_and_hms(2024, 6, 1, 10, 0, 0)
+            .single()
+            .expect("d'oh");
+        assert!(is_usable(now.timestamp() + 60, now));
+        assert!(!is_usable(now.timestamp() + 5, now));
+        assert!(!is_usable(now.timestamp() - 60, now));
+    }
+}
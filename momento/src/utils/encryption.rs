@@ -0,0 +1,355 @@
+use std::path::Path;
+use std::sync::Mutex;
+
+use base64::Engine;
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng},
+    ChaCha20Poly1305, Key,
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::ENV_VAR_NAME_MOMENTO_CREDENTIALS_UNLOCK_SECONDS,
+    error::CliError,
+    utils::{
+        file::{
+            get_unlock_cache_file_path, lines_to_file_content, read_file_lines_if_exists,
+            write_to_file,
+        },
+        secret::SecretString,
+    },
+};
+
+/// How long `momento credentials unlock` unlocks the file for unless told otherwise. Unlocks
+/// from a passphrase prompt are not cached unless `MOMENTO_CREDENTIALS_UNLOCK_SECONDS` is set.
+pub const DEFAULT_UNLOCK_COMMAND_SECONDS: u32 = 15 * 60;
+const ENCRYPTED_FILE_VERSION: u32 = 1;
+/// scrypt parameters for new files: 2^15 iterations with r=8 and p=1 take about 32MiB.
+const SCRYPT_LOG_N: u8 = 15;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+
+/// The scrypt parameters the key of a file is derived from its passphrase with.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct KdfParams {
+    pub log_n: u8,
+    pub r: u32,
+    pub p: u32,
+    pub salt: String,
+}
+
+impl KdfParams {
+    fn new_with_random_salt(log_n: u8) -> Self {
+        let mut salt = [0u8; 16];
+        OsRng.fill_bytes(&mut salt);
+        KdfParams {
+            log_n,
+            r: SCRYPT_R,
+            p: SCRYPT_P,
+            salt: encode(&salt),
+        }
+    }
+
+    fn derive_key(&self, passphrase: &str) -> Result<[u8; 32], CliError> {
+        let params = scrypt::Params::new(self.log_n, self.r, self.p, 32).map_err(|e| CliError {
+            msg: format!("invalid scrypt parameters: {e}"),
+        })?;
+        let mut key = [0u8; 32];
+        scrypt::scrypt(
+            passphrase.as_bytes(),
+            &decode(&self.salt)?,
+            &params,
+            &mut key,
+        )
+        .map_err(|e| CliError {
+            msg: format!("failed to derive the key: {e}"),
+        })?;
+        Ok(key)
+    }
+}
+
+/// A file encrypted with ChaCha20-Poly1305 under a key derived from a passphrase.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct EncryptedFile {
+    pub momento_encrypted: u32,
+    pub kdf: KdfParams,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+/// A key derived from the passphrase of the files encrypted with `kdf`.
+#[derive(Serialize, Deserialize, Clone)]
+struct UnlockedKey {
+    kdf: KdfParams,
+    key: SecretString,
+    valid_until: i64,
+}
+
+/// The key unlocked by this run of the CLI, so that a file is unlocked at most once per run
+/// even when unlocks are not cached.
+static UNLOCKED_KEY: Mutex<Option<UnlockedKey>> = Mutex::new(None);
+
+fn encode(bytes: &[u8]) -> String {
+    base64::engine::general_purpose::STANDARD.encode(bytes)
+}
+
+fn decode(value: &str) -> Result<Vec<u8>, CliError> {
+    base64::engine::general_purpose::STANDARD
+        .decode(value)
+        .map_err(|e| CliError {
+            msg: format!("encrypted file is corrupt: {e}"),
+        })
+}
+
+fn encrypt(plaintext: &str, kdf: KdfParams, key: &[u8; 32]) -> Result<EncryptedFile, CliError> {
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(key));
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext.as_bytes())
+        .map_err(|e| CliError {
+            msg: format!("failed to encrypt: {e}"),
+        })?;
+    Ok(EncryptedFile {
+        momento_encrypted: ENCRYPTED_FILE_VERSION,
+        kdf,
+        nonce: encode(&nonce),
+        ciphertext: encode(&ciphertext),
+    })
+}
+
+fn decrypt(file: &EncryptedFile, key: &[u8; 32]) -> Result<String, CliError> {
+    let nonce = decode(&file.nonce)?;
+    if nonce.len() != 12 {
+        return Err(CliError {
+            msg: "encrypted file is corrupt: invalid nonce".to_string(),
+        });
+    }
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(key));
+    let plaintext = cipher
+        .decrypt(
+            nonce.as_slice().into(),
+            decode(&file.ciphertext)?.as_slice(),
+        )
+        .map_err(|_| CliError {
+            msg: "wrong passphrase".to_string(),
+        })?;
+    String::from_utf8(plaintext).map_err(|e| CliError {
+        msg: format!("encrypted file is corrupt: {e}"),
+    })
+}
+
+/// The file contents as an encrypted file, or `None` for plain text.
+pub fn parse_encrypted_file(contents: &str) -> Option<EncryptedFile> {
+    serde_json::from_str::<EncryptedFile>(contents)
+        .ok()
+        .filter(|file| file.momento_encrypted == ENCRYPTED_FILE_VERSION)
+}
+
+/// How long unlocks are cached for, from `MOMENTO_CREDENTIALS_UNLOCK_SECONDS`. Caching
+/// writes the key to the state directory, so it is off unless the variable is set.
+pub fn unlock_seconds() -> Result<Option<u32>, CliError> {
+    match std::env::var(ENV_VAR_NAME_MOMENTO_CREDENTIALS_UNLOCK_SECONDS) {
+        Ok(seconds) => parse_unlock_seconds(&seconds).map(Some),
+        Err(_) => Ok(None),
+    }
+}
+
+fn parse_unlock_seconds(seconds: &str) -> Result<u32, CliError> {
+    seconds.parse::<u32>().map_err(|_| CliError {
+        msg: format!(
+            "{ENV_VAR_NAME_MOMENTO_CREDENTIALS_UNLOCK_SECONDS} must be a number of seconds up to {}, not {seconds}",
+            u32::MAX
+        ),
+    })
+}
+
+/// The timestamp an unlock made at `now` expires at.
+fn unlock_valid_until(now: DateTime<Utc>, seconds: u32) -> Result<i64, CliError> {
+    now.checked_add_signed(Duration::seconds(seconds.into()))
+        .map(|valid_until| valid_until.timestamp())
+        .ok_or_else(|| CliError {
+            msg: format!("cannot unlock the credentials file for {seconds} seconds"),
+        })
+}
+
+fn decode_key(unlocked: &UnlockedKey) -> Option<[u8; 32]> {
+    decode(unlocked.key.expose_secret()).ok()?.try_into().ok()
+}
+
+async fn cached_key(kdf: &KdfParams) -> Option<[u8; 32]> {
+    let in_process = UNLOCKED_KEY.lock().expect("lock poisoned").clone();
+    if let Some(unlocked) = in_process.filter(|unlocked| &unlocked.kdf == kdf) {
+        return decode_key(&unlocked);
+    }
+    let path = get_unlock_cache_file_path().ok()?;
+    let contents = tokio::fs::read(&path).await.ok()?;
+    let unlocked = serde_json::from_slice::<UnlockedKey>(&contents)
+        .ok()
+        .filter(|unlocked| unlocked.valid_until > Utc::now().timestamp());
+    let Some(unlocked) = unlocked else {
+        // The key must not outlive the unlock, so an expired cache is removed right away.
+        log::debug!("Removing the expired unlock of the credentials file");
+        let _ = tokio::fs::remove_file(&path).await;
+        return None;
+    };
+    if &unlocked.kdf != kdf {
+        return None;
+    }
+    log::debug!("Using the cached unlock of the credentials file");
+    decode_key(&unlocked)
+}
+
+/// Remember `key` for the rest of this run and, for `seconds`, for later runs.
+pub async fn cache_key(kdf: &KdfParams, key: &[u8; 32], seconds: u32) -> Result<(), CliError> {
+    let unlocked = UnlockedKey {
+        kdf: kdf.clone(),
+        key: encode(key).into(),
+        valid_until: unlock_valid_until(Utc::now(), seconds)?,
+    };
+    *UNLOCKED_KEY.lock().expect("lock poisoned") = Some(unlocked.clone());
+    if seconds > 0 {
+        write_to_file(
+            &get_unlock_cache_file_path()?,
+            serde_json::to_string_pretty(&unlocked)?,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Forget the unlocked key, here and in the unlock cache. Returns whether a cached unlock
+/// was removed.
+pub async fn forget_key() -> Result<bool, CliError> {
+    *UNLOCKED_KEY.lock().expect("lock poisoned") = None;
+    let path = get_unlock_cache_file_path()?;
+    if !path.exists() {
+        return Ok(false);
+    }
+    tokio::fs::remove_file(&path).await.map_err(|e| CliError {
+        msg: format!("failed to remove {}, error: {e}", path.display()),
+    })?;
+    Ok(true)
+}
+
+pub fn prompt_for_passphrase(prompt: &str) -> Result<SecretString, CliError> {
+    rpassword::prompt_password(format!("{prompt}: "))
+        .map(SecretString::from)
+        .map_err(|e| CliError {
+            msg: format!("failed to read the passphrase: {e}"),
+        })
+}
+
+/// The key of `file`, if `passphrase` is its passphrase.
+pub fn key_for_passphrase(file: &EncryptedFile, passphrase: &str) -> Result<[u8; 32], CliError> {
+    let key = file.kdf.derive_key(passphrase)?;
+    decrypt(file, &key)?;
+    Ok(key)
+}
+
+/// The key of `file`, prompting for its passphrase if it is not unlocked yet.
+pub async fn unlock_key(path: &Path, file: &EncryptedFile) -> Result<[u8; 32], CliError> {
+    if let Some(key) = cached_key(&file.kdf).await {
+        if decrypt(file, &key).is_ok() {
+            return Ok(key);
+        }
+    }
+    let passphrase = prompt_for_passphrase(&format!("Passphrase for {}", path.display()))?;
+    let key = key_for_passphrase(file, passphrase.expose_secret())?;
+    cache_key(&file.kdf, &key, unlock_seconds()?.unwrap_or(0)).await?;
+    Ok(key)
+}
+
+/// Read the lines of a file that may be encrypted, treating a file that does not exist as
+/// empty.
+pub async fn read_protected_lines(path: &Path) -> Result<Vec<String>, CliError> {
+    let lines = read_file_lines_if_exists(path).await?;
+    match parse_encrypted_file(&lines.join("\n")) {
+        Some(file) => {
+            let key = unlock_key(path, &file).await?;
+            Ok(decrypt(&file, &key)?
+                .lines()
+                .map(|l| l.to_string())
+                .collect())
+        }
+        None => Ok(lines),
+    }
+}
+
+/// Replace the lines of a file, encrypting them again if the file is encrypted.
+pub async fn write_protected_lines(path: &Path, lines: Vec<String>) -> Result<(), CliError> {
+    let contents = lines_to_file_content(lines);
+    let existing = read_file_lines_if_exists(path).await?;
+    match parse_encrypted_file(&existing.join("\n")) {
+        Some(file) => {
+            let key = unlock_key(path, &file).await?;
+            write_encrypted(path, &contents, file.kdf, &key).await
+        }
+        None => write_to_file(path, contents).await,
+    }
+}
+
+/// Encrypt `contents` into the file at `path` under `key`.
+pub async fn write_encrypted(
+    path: &Path,
+    contents: &str,
+    kdf: KdfParams,
+    key: &[u8; 32],
+) -> Result<(), CliError> {
+    let file = encrypt(contents, kdf, key)?;
+    write_to_file(path, serde_json::to_string_pretty(&file)?).await
+}
+
+/// Derive a key for a new encrypted file from `passphrase`.
+pub fn new_key(passphrase: &str) -> Result<(KdfParams, [u8; 32]), CliError> {
+    let kdf = KdfParams::new_with_random_salt(SCRYPT_LOG_N);
+    let key = kdf.derive_key(passphrase)?;
+    Ok((kdf, key))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use crate::utils::encryption::{
+        decrypt, encrypt, parse_encrypted_file, parse_unlock_seconds, unlock_valid_until, KdfParams,
+    };
+
+    #[test]
+    fn unlock_seconds_are_bounded() {
+        assert_eq!(3600, parse_unlock_seconds("3600").expect("d'oh"));
+        assert!(parse_unlock_seconds("-1").is_err());
+        assert!(parse_unlock_seconds("9223372036854775807").is_err());
+        let now = Utc.timestamp_opt(1700000000, 0).single().expect("d'oh");
+        assert_eq!(
+            1700000000 + i64::from(u32::MAX),
+            unlock_valid_until(now, u32::MAX).expect("d'oh")
+        );
+    }
+
+    #[test]
+    fn encrypted_files_round_trip_only_with_the_right_passphrase() {
+        // A cheap key derivation, to keep the test fast.
+        let kdf = KdfParams::new_with_random_salt(4);
+        let key = kdf.derive_key("correct horse").expect("d'oh");
+        let contents = "[default]\ntoken=awesome-token\n";
+        let file = encrypt(contents, kdf.clone(), &key).expect("d'oh");
+
+        let serialized = serde_json::to_string_pretty(&file).expect("d'oh");
+        assert!(!serialized.contains("awesome-token"));
+        let parsed = parse_encrypted_file(&serialized).expect("d'oh");
+        assert_eq!(contents, decrypt(&parsed, &key).expect("d'oh"));
+
+        let wrong_key = kdf.derive_key("battery staple").expect("d'oh");
+        assert_eq!(
+            "wrong passphrase",
+            decrypt(&parsed, &wrong_key).expect_err("d'oh").msg
+        );
+    }
+
+    #[test]
+    fn ini_files_are_not_encrypted_files() {
+        assert!(parse_encrypted_file("[default]\ntoken=awesome-token").is_none());
+        assert!(parse_encrypted_file("").is_none());
+    }
+}
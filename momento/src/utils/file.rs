@@ -1,5 +1,7 @@
 use crate::config::ENV_VAR_NAME_MOMENTO_CONFIG_DIR;
-use configparser::ini::Ini;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
 use home::home_dir;
 use log::debug;
 use tokio::{
@@ -7,53 +9,139 @@ use tokio::{
     io::{self, AsyncBufReadExt, AsyncWriteExt, BufReader},
 };
 
-use crate::error::CliError;
+use crate::{error::CliError, utils::ini_config::IniDocument};
+
+const CREDENTIALS_FILE_NAME: &str = "credentials";
+const CONFIG_FILE_NAME: &str = "config";
+const TOML_CONFIG_FILE_NAME: &str = "config.toml";
+const SESSIONS_FILE_NAME: &str = "sessions";
+const CREDENTIAL_PROCESS_CACHE_FILE_NAME: &str = "credential_process_cache.json";
+const UNLOCK_CACHE_FILE_NAME: &str = "credentials_unlock.json";
+/// The file `lock_config_dir` locks, which is left behind in the config directory.
+pub const LOCK_FILE_NAME: &str = "config.lock";
+
+/// The files kept in the config directory.
+pub const CONFIG_DIR_FILE_NAMES: [&str; 3] = [
+    CREDENTIALS_FILE_NAME,
+    CONFIG_FILE_NAME,
+    TOML_CONFIG_FILE_NAME,
+];
+/// The files kept in the state directory, which the CLI can recreate.
+pub const STATE_DIR_FILE_NAMES: [&str; 3] = [
+    SESSIONS_FILE_NAME,
+    CREDENTIAL_PROCESS_CACHE_FILE_NAME,
+    UNLOCK_CACHE_FILE_NAME,
+];
 
-// FIXME All of this stuff should be using pathbuf and not concatenating strings with /'s...
-pub fn get_credentials_file_path() -> Result<String, CliError> {
-    let momento_home = get_momento_config_dir()?;
-    Ok(format!("{momento_home}/credentials"))
+const ENV_VAR_NAME_XDG_CONFIG_HOME: &str = "XDG_CONFIG_HOME";
+const ENV_VAR_NAME_XDG_STATE_HOME: &str = "XDG_STATE_HOME";
+
+pub fn get_credentials_file_path() -> Result<PathBuf, CliError> {
+    Ok(get_momento_config_dir()?.join(CREDENTIALS_FILE_NAME))
 }
 
-pub fn get_config_file_path() -> Result<String, CliError> {
-    let momento_home = get_momento_config_dir()?;
-    Ok(format!("{momento_home}/config"))
+pub fn get_config_file_path() -> Result<PathBuf, CliError> {
+    Ok(get_momento_config_dir()?.join(CONFIG_FILE_NAME))
 }
 
-pub fn get_momento_config_dir() -> Result<String, CliError> {
-    let env_var = std::env::var(ENV_VAR_NAME_MOMENTO_CONFIG_DIR);
+pub fn get_toml_config_file_path() -> Result<PathBuf, CliError> {
+    Ok(get_momento_config_dir()?.join(TOML_CONFIG_FILE_NAME))
+}
 
-    if let Ok(val) = env_var {
-        return Ok(val);
-    }
-    // If the env var isn't set we default to ~/.momento
-    let home = home_dir().ok_or_else(|| CliError {
+pub fn get_sessions_file_path() -> Result<PathBuf, CliError> {
+    Ok(get_momento_state_dir()?.join(SESSIONS_FILE_NAME))
+}
+
+pub fn get_credential_process_cache_file_path() -> Result<PathBuf, CliError> {
+    Ok(get_momento_state_dir()?.join(CREDENTIAL_PROCESS_CACHE_FILE_NAME))
+}
+
+pub fn get_unlock_cache_file_path() -> Result<PathBuf, CliError> {
+    Ok(get_momento_state_dir()?.join(UNLOCK_CACHE_FILE_NAME))
+}
+
+fn home() -> Result<PathBuf, CliError> {
+    home_dir().ok_or_else(|| CliError {
         msg: "could not find home dir".to_string(),
-    })?;
-    Ok(format!("{}/.momento", home.display()))
+    })
+}
+
+/// The directory every file lived in before the XDG directories were supported.
+pub fn get_legacy_momento_dir() -> Result<PathBuf, CliError> {
+    Ok(home()?.join(".momento"))
+}
+
+/// `momento` inside an XDG base directory, which is `xdg_home` when it is an absolute path
+/// and `default` inside the home directory otherwise.
+fn xdg_momento_dir(home: &Path, xdg_home: Option<String>, default: &str) -> PathBuf {
+    xdg_home
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .unwrap_or_else(|| home.join(default))
+        .join("momento")
+}
+
+pub fn get_xdg_config_dir() -> Result<PathBuf, CliError> {
+    let xdg_home = std::env::var(ENV_VAR_NAME_XDG_CONFIG_HOME).ok();
+    Ok(xdg_momento_dir(&home()?, xdg_home, ".config"))
+}
+
+pub fn get_xdg_state_dir() -> Result<PathBuf, CliError> {
+    let xdg_home = std::env::var(ENV_VAR_NAME_XDG_STATE_HOME).ok();
+    Ok(xdg_momento_dir(&home()?, xdg_home, ".local/state"))
 }
 
-pub async fn open_file(path: &str) -> Result<File, CliError> {
+/// `MOMENTO_CONFIG_DIR` when it is set, `~/.momento` while it exists, and the XDG
+/// directory otherwise.
+fn momento_dir(momento_config_dir: Option<String>, home: &Path, xdg_dir: PathBuf) -> PathBuf {
+    if let Some(dir) = momento_config_dir.filter(|dir| !dir.is_empty()) {
+        return PathBuf::from(dir);
+    }
+    let legacy_dir = home.join(".momento");
+    if legacy_dir.is_dir() {
+        return legacy_dir;
+    }
+    xdg_dir
+}
+
+pub fn get_momento_config_dir() -> Result<PathBuf, CliError> {
+    let momento_config_dir = std::env::var(ENV_VAR_NAME_MOMENTO_CONFIG_DIR).ok();
+    Ok(momento_dir(
+        momento_config_dir,
+        &home()?,
+        get_xdg_config_dir()?,
+    ))
+}
+
+/// Where login sessions and cached tokens are kept. This is the config directory unless
+/// the XDG directories are used.
+pub fn get_momento_state_dir() -> Result<PathBuf, CliError> {
+    let momento_config_dir = std::env::var(ENV_VAR_NAME_MOMENTO_CONFIG_DIR).ok();
+    Ok(momento_dir(
+        momento_config_dir,
+        &home()?,
+        get_xdg_state_dir()?,
+    ))
+}
+
+pub async fn open_file(path: &Path) -> Result<File, CliError> {
     let res = File::open(path).await;
     match res {
         Ok(f) => {
-            debug!("opened file {path}");
+            debug!("opened file {}", path.display());
             Ok(f)
         }
         Err(e) => Err(CliError {
-            msg: format!("failed to create file {path}, error: {e}"),
+            msg: format!("failed to create file {}, error: {e}", path.display()),
         }),
     }
 }
 
-pub async fn read_ini_file(path: &str) -> Result<Ini, CliError> {
-    let mut config = Ini::new_cs();
-    match config.load(path) {
-        Ok(_) => Ok(config),
-        Err(e) => Err(CliError {
-            msg: format!("failed to read file: {e}"),
-        }),
-    }
+pub async fn read_ini_file(path: &Path) -> Result<IniDocument, CliError> {
+    let contents = fs::read_to_string(path).await.map_err(|e| CliError {
+        msg: format!("failed to read file {}: {e}", path.display()),
+    })?;
+    Ok(IniDocument::parse(&contents.lines().collect::<Vec<_>>()))
 }
 
 pub async fn read_file_contents(file: File) -> Result<Vec<String>, CliError> {
@@ -69,41 +157,168 @@ pub async fn read_file_contents(file: File) -> Result<Vec<String>, CliError> {
     Ok(file_contents)
 }
 
-pub async fn create_file(path: &str) -> Result<(), CliError> {
-    let res = File::create(path).await;
+/// Read the lines of a file, treating a file that does not exist as empty.
+pub async fn read_file_lines_if_exists(path: &Path) -> Result<Vec<String>, CliError> {
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    let file = open_file(path).await?;
+    read_file_contents(file).await
+}
+
+pub fn lines_to_file_content(lines: Vec<String>) -> String {
+    // ensure a single trailing newline
+    format!("{}\n", lines.join("\n").trim_end())
+}
+
+/// Options for opening files that may hold tokens, which only the current user can read.
+fn private_file_options() -> fs::OpenOptions {
+    #[allow(unused_mut)]
+    let mut options = fs::OpenOptions::new();
+    #[cfg(unix)]
+    options.mode(0o600);
+    options
+}
+
+pub async fn create_file(path: &Path) -> Result<(), CliError> {
+    let res = private_file_options()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)
+        .await;
     match res {
         Ok(_) => {
-            debug!("created file {}", path);
+            debug!("created file {}", path.display());
             Ok(())
         }
         Err(e) => Err(CliError {
-            msg: format!("failed to create file {path}, error: {e}"),
+            msg: format!("failed to create file {}, error: {e}", path.display()),
         }),
     }
 }
 
-pub async fn write_to_file(path: &str, file_contents: String) -> Result<(), CliError> {
-    let mut file = match fs::File::create(path).await {
-        Ok(f) => f,
-        Err(e) => {
-            return Err(CliError {
-                msg: format!("failed to write to file {path}, error: {e}"),
-            })
+/// Replace the contents of `path` without ever leaving a partially written file behind.
+/// The contents are written and synced to a temporary file in the same directory, which
+/// is then renamed over `path`, and on unix the directory is synced so that the rename
+/// survives a crash. The directory is created if needed.
+pub async fn write_to_file(path: &Path, file_contents: String) -> Result<(), CliError> {
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(format!(".{}.tmp", std::process::id()));
+    let written = async {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).await?;
         }
-    };
+        let mut file = private_file_options()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)
+            .await?;
+        file.write_all(file_contents.as_bytes()).await?;
+        file.sync_all().await?;
+        fs::rename(&temp_path, path).await?;
+        // The rename is only durable once the directory entry is on disk too.
+        #[cfg(unix)]
+        if let Some(dir) = path.parent() {
+            File::open(dir).await?.sync_all().await?;
+        }
+        Ok::<_, std::io::Error>(())
+    }
+    .await;
 
-    // Write to file
+    if let Err(e) = written {
+        let _ = fs::remove_file(&temp_path).await;
+        return Err(CliError {
+            msg: format!("failed to write to file {}, error: {e}", path.display()),
+        });
+    }
+    Ok(())
+}
 
-    match file.write(file_contents.as_bytes()).await {
-        Ok(_) => {}
-        Err(e) => {
-            return Err(CliError {
-                msg: format!("failed to write to file {path}, error: {e}"),
-            })
+/// Move a file, copying it when it cannot be renamed, such as across file systems.
+pub async fn move_file(from: &Path, to: &Path) -> Result<(), CliError> {
+    if fs::rename(from, to).await.is_ok() {
+        return Ok(());
+    }
+    let moved = async {
+        fs::copy(from, to).await?;
+        fs::remove_file(from).await
+    }
+    .await;
+    moved.map_err(|e| CliError {
+        msg: format!(
+            "failed to move {} to {}, error: {e}",
+            from.display(),
+            to.display()
+        ),
+    })
+}
+
+/// The lock file while this process holds the config directory lock, and how many
+/// `ConfigDirLock`s share it.
+static HELD_LOCK: Mutex<Option<(std::fs::File, usize)>> = Mutex::new(None);
+
+/// An advisory lock on the Momento config directory, released when the last
+/// `ConfigDirLock` of this process is dropped.
+pub struct ConfigDirLock {
+    _private: (),
+}
+
+impl Drop for ConfigDirLock {
+    fn drop(&mut self) {
+        let mut held = HELD_LOCK.lock().expect("lock poisoned");
+        if let Some((_, count)) = held.as_mut() {
+            *count -= 1;
+            if *count == 0 {
+                // Closing the file releases the lock.
+                *held = None;
+            }
         }
-    };
+    }
+}
 
-    Ok(())
+/// Lock the config directory for a read-modify-write cycle of the files in it, so that
+/// concurrent CLI runs do not overwrite each other's changes. Waits for other runs to
+/// finish their cycle. The lock is re-entrant within a process, so helpers can take it
+/// while their caller holds it.
+pub async fn lock_config_dir() -> Result<ConfigDirLock, CliError> {
+    lock_dir(&get_momento_config_dir()?).await
+}
+
+async fn lock_dir(dir: &Path) -> Result<ConfigDirLock, CliError> {
+    if let Some((_, count)) = HELD_LOCK.lock().expect("lock poisoned").as_mut() {
+        *count += 1;
+        return Ok(ConfigDirLock { _private: () });
+    }
+    fs::create_dir_all(dir).await.map_err(|e| CliError {
+        msg: format!("failed to create directory: {e}"),
+    })?;
+    let path = dir.join(LOCK_FILE_NAME);
+    let file = private_file_options()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)
+        .await
+        .map_err(|e| CliError {
+            msg: format!("failed to open lock file {}, error: {e}", path.display()),
+        })?
+        .into_std()
+        .await;
+    let lock_failed = |e: &dyn std::fmt::Display| CliError {
+        msg: format!("failed to lock {}, error: {e}", path.display()),
+    };
+    let file = tokio::task::spawn_blocking(move || file.lock().map(|_| file))
+        .await
+        .map_err(|e| lock_failed(&e))?
+        .map_err(|e| lock_failed(&e))?;
+    let mut held = HELD_LOCK.lock().expect("lock poisoned");
+    match held.as_mut() {
+        // Another task of this process locked it meanwhile; share that lock instead.
+        Some((_, count)) => *count += 1,
+        None => *held = Some((file, 1)),
+    }
+    Ok(ConfigDirLock { _private: () })
 }
 
 pub async fn prompt_user_for_input(
@@ -155,3 +370,88 @@ pub async fn prompt_user_for_input(
     }
     Ok(input)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::utils::file::{lock_dir, momento_dir, write_to_file, xdg_momento_dir};
+
+    #[tokio::test]
+    async fn lock_dir_is_reentrant() {
+        let dir =
+            std::env::temp_dir().join(format!("momento-cli-test-lock-{}", std::process::id()));
+        let nested = async {
+            let outer = lock_dir(&dir).await.expect("d'oh");
+            let inner = lock_dir(&dir).await.expect("d'oh");
+            drop(inner);
+            drop(outer);
+            lock_dir(&dir).await.expect("d'oh");
+        };
+        tokio::time::timeout(std::time::Duration::from_secs(10), nested)
+            .await
+            .expect("nested locks deadlocked");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn write_to_file_replaces_contents_and_leaves_no_temp_file() {
+        let dir =
+            std::env::temp_dir().join(format!("momento-cli-test-write-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("d'oh");
+        let path = dir.join("credentials");
+
+        write_to_file(
+            &path,
+            "[default]\ntoken=a-much-longer-old-token\n".to_string(),
+        )
+        .await
+        .expect("d'oh");
+        write_to_file(&path, "[default]\ntoken=new\n".to_string())
+            .await
+            .expect("d'oh");
+
+        assert_eq!(
+            "[default]\ntoken=new\n",
+            std::fs::read_to_string(&path).expect("d'oh")
+        );
+        let entries: Vec<_> = std::fs::read_dir(&dir).expect("d'oh").collect();
+        assert_eq!(1, entries.len());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).expect("d'oh").permissions().mode();
+            assert_eq!(0o600, mode & 0o777);
+        }
+        std::fs::remove_dir_all(&dir).expect("d'oh");
+    }
+
+    #[test]
+    fn momento_dir_prefers_the_override_then_the_legacy_dir() {
+        let home =
+            std::env::temp_dir().join(format!("momento-cli-test-home-{}", std::process::id()));
+        std::fs::create_dir_all(&home).expect("d'oh");
+        let xdg_dir = xdg_momento_dir(&home, None, ".config");
+        assert_eq!(home.join(".config/momento"), xdg_dir);
+        assert_eq!(
+            PathBuf::from("/xdg/config/momento"),
+            xdg_momento_dir(&home, Some("/xdg/config".to_string()), ".config")
+        );
+        assert_eq!(
+            xdg_dir,
+            xdg_momento_dir(&home, Some("relative/config".to_string()), ".config")
+        );
+
+        assert_eq!(xdg_dir, momento_dir(None, &home, xdg_dir.clone()));
+        std::fs::create_dir_all(home.join(".momento")).expect("d'oh");
+        assert_eq!(
+            home.join(".momento"),
+            momento_dir(None, &home, xdg_dir.clone())
+        );
+        assert_eq!(
+            PathBuf::from("/override"),
+            momento_dir(Some("/override".to_string()), &home, xdg_dir.clone())
+        );
+        std::fs::remove_dir_all(&home).expect("d'oh");
+    }
+}
@@ -0,0 +1,192 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    config::{parse_config_number, Config, CONFIG_KEYS},
+    error::CliError,
+};
+
+/// The section holding the settings every profile falls back to.
+pub const DEFAULTS_SECTION: &str = "defaults";
+/// Names the profile that a profile takes the settings it does not set from.
+pub const INHERITS_KEY: &str = "inherits";
+
+/// An effective setting of a profile and the section it was found in.
+#[derive(Debug, PartialEq)]
+pub struct ResolvedSetting {
+    pub value: String,
+    pub source: String,
+}
+
+/// The sections the settings of `profile` are looked up in, nearest first: the profile
+/// itself, the profiles it inherits from, and finally the defaults section. The profile
+/// must exist, so that a mistyped name is not quietly resolved from the defaults alone.
+pub fn inheritance_chain(
+    profile: &str,
+    has_section: impl Fn(&str) -> bool,
+    parent_of: impl Fn(&str) -> Option<String>,
+) -> Result<Vec<String>, CliError> {
+    if !has_section(profile) {
+        return Err(CliError {
+            msg: format!("profile {profile} does not exist, please run 'momento configure --profile {profile}' to configure it"),
+        });
+    }
+    let mut chain = vec![profile.to_string()];
+    while let Some(parent) = parent_of(&chain[chain.len() - 1]).filter(|p| !p.is_empty()) {
+        if chain.contains(&parent) {
+            chain.push(parent);
+            return Err(CliError {
+                msg: format!("profile inheritance cycle: {}", chain.join(" -> ")),
+            });
+        }
+        if !has_section(&parent) {
+            return Err(CliError {
+                msg: format!(
+                    "profile {} inherits from profile {parent}, which does not exist",
+                    chain[chain.len() - 1]
+                ),
+            });
+        }
+        chain.push(parent);
+    }
+    if has_section(DEFAULTS_SECTION) && !chain.iter().any(|s| s == DEFAULTS_SECTION) {
+        chain.push(DEFAULTS_SECTION.to_string());
+    }
+    Ok(chain)
+}
+
+/// Look up every `Config` setting along `chain`, taking the first value that is set.
+pub fn resolve_settings(
+    chain: &[String],
+    get: impl Fn(&str, &str) -> Option<String>,
+) -> BTreeMap<String, ResolvedSetting> {
+    let mut settings = BTreeMap::new();
+    for key in CONFIG_KEYS {
+        let found = chain.iter().find_map(|section| {
+            get(section, key)
+                .filter(|value| !value.is_empty())
+                .map(|value| ResolvedSetting {
+                    value,
+                    source: section.clone(),
+                })
+        });
+        if let Some(setting) = found {
+            settings.insert(key.to_string(), setting);
+        }
+    }
+    settings
+}
+
+fn missing_setting(profile: &str, setting: &str) -> CliError {
+    CliError {
+        msg: format!("failed to get {setting} config for profile {profile}, please run 'momento configure' to configure your profile"),
+    }
+}
+
+pub fn config_from_settings(
+    profile: &str,
+    settings: &BTreeMap<String, ResolvedSetting>,
+) -> Result<Config, CliError> {
+    let value = |key: &str| settings.get(key).map(|setting| setting.value.clone());
+    let number = |key: &str| {
+        value(key)
+            .map(|value| parse_config_number(key, &value))
+            .transpose()
+    };
+    Ok(Config {
+        cache: value("cache").ok_or_else(|| missing_setting(profile, "cache"))?,
+        ttl: number("ttl")?.ok_or_else(|| missing_setting(profile, "ttl"))?,
+        endpoint: value("endpoint"),
+        output: value("output").map(|o| o.parse()).transpose()?,
+        request_timeout_seconds: number("request_timeout_seconds")?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::utils::inheritance::{config_from_settings, inheritance_chain, resolve_settings};
+
+    fn sections(entries: &[(&str, &[(&str, &str)])]) -> HashMap<String, HashMap<String, String>> {
+        entries
+            .iter()
+            .map(|(section, values)| {
+                (
+                    section.to_string(),
+                    values
+                        .iter()
+                        .map(|(k, v)| (k.to_string(), v.to_string()))
+                        .collect(),
+                )
+            })
+            .collect()
+    }
+
+    fn chain_of(
+        sections: &HashMap<String, HashMap<String, String>>,
+        profile: &str,
+    ) -> Result<Vec<String>, crate::error::CliError> {
+        inheritance_chain(
+            profile,
+            |name| sections.contains_key(name),
+            |name| sections.get(name).and_then(|s| s.get("inherits").cloned()),
+        )
+    }
+
+    #[test]
+    fn settings_come_from_the_nearest_section() {
+        let sections = sections(&[
+            (
+                "defaults",
+                &[("ttl", "600"), ("endpoint", "cell-1.example.com")],
+            ),
+            ("base", &[("cache", "base-cache"), ("ttl", "90")]),
+            ("prod", &[("inherits", "base"), ("cache", "prod-cache")]),
+        ]);
+        let chain = chain_of(&sections, "prod").expect("d'oh");
+        assert_eq!(vec!["prod", "base", "defaults"], chain);
+
+        let settings = resolve_settings(&chain, |section, key| {
+            sections.get(section).and_then(|s| s.get(key).cloned())
+        });
+        assert_eq!("prod", settings["cache"].source);
+        assert_eq!("base", settings["ttl"].source);
+        assert_eq!("defaults", settings["endpoint"].source);
+
+        let config = config_from_settings("prod", &settings).expect("d'oh");
+        assert_eq!("prod-cache", config.cache);
+        assert_eq!(90, config.ttl);
+        assert_eq!(Some("cell-1.example.com".to_string()), config.endpoint);
+    }
+
+    #[test]
+    fn inheritance_cycles_and_missing_parents_are_errors() {
+        let sections = sections(&[
+            ("a", &[("inherits", "b")]),
+            ("b", &[("inherits", "a")]),
+            ("c", &[("inherits", "nope")]),
+        ]);
+        assert_eq!(
+            "profile inheritance cycle: a -> b -> a",
+            chain_of(&sections, "a").expect_err("d'oh").msg
+        );
+        assert_eq!(
+            "profile c inherits from profile nope, which does not exist",
+            chain_of(&sections, "c").expect_err("d'oh").msg
+        );
+        assert_eq!(
+            "profile nope does not exist, please run 'momento configure --profile nope' to configure it",
+            chain_of(&sections, "nope").expect_err("d'oh").msg
+        );
+    }
+
+    #[test]
+    fn config_requires_cache_and_ttl() {
+        let sections = sections(&[("default", &[("cache", "default-cache")])]);
+        let chain = chain_of(&sections, "default").expect("d'oh");
+        let settings = resolve_settings(&chain, |section, key| {
+            sections.get(section).and_then(|s| s.get(key).cloned())
+        });
+        assert!(config_from_settings("default", &settings).is_err());
+    }
+}
@@ -1,35 +1,256 @@
-use lazy_static::lazy_static;
-use regex::Regex;
-
 use crate::{
     config::{Config, Credentials},
     error::CliError,
+    utils::{
+        inheritance::{DEFAULTS_SECTION, INHERITS_KEY},
+        secret::SecretString,
+    },
 };
 
-lazy_static! {
-    static ref REGEX_PROFILE_HEADER: Regex =
-        Regex::new("^\\s*\\[[^\\]]+\\]\\s*$").expect("Unable to compile profile header regex");
-    static ref REGEX_CONFIG_FILE_CACHE_SETTING: Regex = Regex::new(r"^cache\s*=\s*([\w-]*)\s*$")
-        .expect("Unable to compile config file cache setting regex");
-    static ref REGEX_CONFIG_FILE_TTL_SETTING: Regex = Regex::new(r"^ttl\s*=\s*([\d]*)\s*$")
-        .expect("Unable to compile config file ttl setting regex");
-    static ref REGEX_CREDS_FILE_TOKEN_SETTING: Regex = Regex::new(r"^token\s*=\s*([\w\.-]*)\s*$")
-        .expect("Unable to compile creds file token regex");
+/// One line of an INI file. Every line keeps its original text so that a file
+/// is written back exactly as it was read, apart from the values that changed.
+#[derive(Debug, Clone, PartialEq)]
+enum IniLine {
+    /// A blank line, a comment, or anything else that is not understood.
+    Other(String),
+    Section {
+        raw: String,
+        name: String,
+    },
+    Entry {
+        raw: String,
+        key: String,
+        /// Everything up to the value, e.g. `token = `, reused when the value changes.
+        prefix: String,
+        value: String,
+    },
+}
+
+impl IniLine {
+    fn parse(line: &str) -> Self {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with(';') {
+            return IniLine::Other(line.to_string());
+        }
+        if trimmed.starts_with('[') && trimmed.ends_with(']') {
+            return IniLine::Section {
+                raw: line.to_string(),
+                name: trimmed[1..trimmed.len() - 1].trim().to_string(),
+            };
+        }
+        match line.find(['=', ':']) {
+            Some(delimiter) if !line[..delimiter].trim().is_empty() => {
+                let after_delimiter = &line[delimiter + 1..];
+                let value_start =
+                    delimiter + 1 + after_delimiter.len() - after_delimiter.trim_start().len();
+                IniLine::Entry {
+                    raw: line.to_string(),
+                    key: line[..delimiter].trim().to_string(),
+                    prefix: line[..value_start].to_string(),
+                    value: line[value_start..].trim_end().to_string(),
+                }
+            }
+            _ => IniLine::Other(line.to_string()),
+        }
+    }
+
+    fn entry(key: &str, value: &str) -> Self {
+        IniLine::parse(&format!("{key}={value}"))
+    }
+
+    fn section(name: &str) -> Self {
+        IniLine::parse(&format!("[{name}]"))
+    }
+
+    fn raw(&self) -> &str {
+        match self {
+            IniLine::Other(raw) => raw,
+            IniLine::Section { raw, .. } => raw,
+            IniLine::Entry { raw, .. } => raw,
+        }
+    }
+
+    fn is_blank(&self) -> bool {
+        self.raw().trim().is_empty()
+    }
+
+    fn is_section(&self, section_name: &str) -> bool {
+        matches!(self, IniLine::Section { name, .. } if name == section_name)
+    }
+
+    fn is_entry(&self, entry_key: &str) -> bool {
+        matches!(self, IniLine::Entry { key, .. } if key == entry_key)
+    }
+}
+
+/// An INI file that can be edited without losing comments, blank lines, key order
+/// or keys this CLI does not know about.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IniDocument {
+    lines: Vec<IniLine>,
+}
+
+impl IniDocument {
+    pub fn parse(file_contents: &[impl AsRef<str>]) -> Self {
+        IniDocument {
+            lines: file_contents
+                .iter()
+                .map(|l| IniLine::parse(l.as_ref()))
+                .collect(),
+        }
+    }
+
+    pub fn into_lines(self) -> Vec<String> {
+        self.lines
+            .into_iter()
+            .map(|line| match line {
+                IniLine::Other(raw) => raw,
+                IniLine::Section { raw, .. } => raw,
+                IniLine::Entry { raw, .. } => raw,
+            })
+            .collect()
+    }
+
+    pub fn section_names(&self) -> Vec<String> {
+        self.lines
+            .iter()
+            .filter_map(|line| match line {
+                IniLine::Section { name, .. } => Some(name.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn has_section(&self, section: &str) -> bool {
+        self.lines.iter().any(|line| line.is_section(section))
+    }
+
+    /// The index of the section header and the index just past the end of the section.
+    fn section_range(&self, section: &str) -> Option<(usize, usize)> {
+        let start = self
+            .lines
+            .iter()
+            .position(|line| line.is_section(section))?;
+        let end = self.lines[start + 1..]
+            .iter()
+            .position(|line| matches!(line, IniLine::Section { .. }))
+            .map_or(self.lines.len(), |offset| start + 1 + offset);
+        Some((start, end))
+    }
+
+    fn entry_index(&self, section: &str, key: &str) -> Option<usize> {
+        let (start, end) = self.section_range(section)?;
+        (start + 1..end).find(|i| self.lines[*i].is_entry(key))
+    }
+
+    pub fn get(&self, section: &str, key: &str) -> Option<&str> {
+        match &self.lines[self.entry_index(section, key)?] {
+            IniLine::Entry { value, .. } => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Set `key` in `section`, adding the key or the section if they do not exist yet.
+    pub fn set(&mut self, section: &str, key: &str, value: &str) {
+        if let Some(i) = self.entry_index(section, key) {
+            if let IniLine::Entry { prefix, .. } = &self.lines[i] {
+                let mut updated = prefix.clone();
+                updated.push_str(value);
+                self.lines[i] = IniLine::parse(&updated);
+            }
+            return;
+        }
+        match self.section_range(section) {
+            Some((start, end)) => {
+                // Add new keys after the last line of the section rather than after any blank lines.
+                let insert_at = (start + 1..end)
+                    .rev()
+                    .find(|i| !self.lines[*i].is_blank())
+                    .map_or(start + 1, |i| i + 1);
+                self.lines.insert(insert_at, IniLine::entry(key, value));
+            }
+            None => {
+                self.append_section(vec![IniLine::section(section), IniLine::entry(key, value)])
+            }
+        }
+    }
+
+    /// Remove `key` from `section`, returning whether it was there.
+    pub fn remove(&mut self, section: &str, key: &str) -> bool {
+        match self.entry_index(section, key) {
+            Some(i) => {
+                self.lines.remove(i);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Whether the section is missing or has nothing but blank lines and comments.
+    pub fn is_section_empty(&self, section: &str) -> bool {
+        match self.section_range(section) {
+            Some((start, end)) => !self.lines[start + 1..end]
+                .iter()
+                .any(|line| matches!(line, IniLine::Entry { .. })),
+            None => true,
+        }
+    }
+
+    pub fn remove_section(&mut self, section: &str) {
+        if let Some((start, end)) = self.section_range(section) {
+            self.lines.drain(start..end);
+        }
+    }
+
+    pub fn rename_section(&mut self, section: &str, new_name: &str) {
+        if let Some((start, _)) = self.section_range(section) {
+            self.lines[start] = IniLine::section(new_name);
+        }
+    }
+
+    /// Append a copy of `section`, including its comments, named `new_name`.
+    pub fn copy_section(&mut self, section: &str, new_name: &str) {
+        if let Some((start, end)) = self.section_range(section) {
+            let mut copy = vec![IniLine::section(new_name)];
+            copy.extend(self.lines[start + 1..end].iter().cloned());
+            trim_trailing_blank_lines(&mut copy);
+            self.append_section(copy);
+        }
+    }
+
+    /// Append a section, separated from the previous section by one blank line.
+    fn append_section(&mut self, section: Vec<IniLine>) {
+        trim_trailing_blank_lines(&mut self.lines);
+        if !self.lines.is_empty() {
+            self.lines.push(IniLine::Other("".to_string()));
+        }
+        self.lines.extend(section);
+    }
+}
+
+fn trim_trailing_blank_lines(lines: &mut Vec<IniLine>) {
+    while lines.last().is_some_and(|l| l.is_blank()) {
+        lines.pop();
+    }
 }
 
 pub fn create_new_credentials_profile(profile_name: &str, credentials: Credentials) -> Vec<String> {
     vec![
         format!("[{profile_name}]"),
-        format!("token={}", credentials.token),
+        format!("token={}", credentials.token.expose_secret()),
     ]
 }
 
 pub fn create_new_config_profile(profile_name: &str, config: Config) -> Vec<String> {
-    vec![
+    let mut profile = vec![
         format!("[{profile_name}]"),
         format!("cache={}", config.cache),
         format!("ttl={}", config.ttl),
-    ]
+    ];
+    if let Some(endpoint) = config.endpoint {
+        profile.push(format!("endpoint={endpoint}"));
+    }
+    profile
 }
 
 pub fn update_credentials_profile(
@@ -37,20 +258,37 @@ pub fn update_credentials_profile(
     file_contents: &[impl AsRef<str>],
     credentials: Credentials,
 ) -> Result<Vec<String>, CliError> {
-    let (profile_start_line, profile_end_line) =
-        find_line_numbers_for_profile(file_contents, profile_name);
-    let mut updated_file_contents: Vec<String> = file_contents
-        .iter()
-        .map(|l| l.as_ref().to_string())
-        .collect();
-    for l in updated_file_contents
-        .iter_mut()
-        .take(profile_end_line)
-        .skip(profile_start_line)
+    let mut document = IniDocument::parse(file_contents);
+    document.set(profile_name, "token", credentials.token.expose_secret());
+    Ok(document.into_lines())
+}
+
+/// Store a token that Momento generated for the profile, along with its refresh token and
+/// expiry. The token it replaces is kept as `previous_token`.
+pub fn update_generated_token_profile(
+    profile_name: &str,
+    file_contents: &[impl AsRef<str>],
+    token: &SecretString,
+    refresh_token: &SecretString,
+    valid_until: Option<&str>,
+) -> Vec<String> {
+    let mut document = IniDocument::parse(file_contents);
+    if let Some(previous) = document
+        .get(profile_name, "token")
+        .filter(|previous| !previous.is_empty() && *previous != token.expose_secret())
+        .map(|previous| previous.to_string())
     {
-        *l = replace_credentials_value(l, &credentials)
+        document.set(profile_name, "previous_token", &previous);
     }
-    Ok(updated_file_contents)
+    document.set(profile_name, "token", token.expose_secret());
+    document.set(profile_name, "refresh_token", refresh_token.expose_secret());
+    match valid_until {
+        Some(valid_until) => document.set(profile_name, "token_valid_until", valid_until),
+        None => {
+            document.remove(profile_name, "token_valid_until");
+        }
+    }
+    document.into_lines()
 }
 
 pub fn update_config_profile<T: AsRef<str>>(
@@ -58,107 +296,116 @@ pub fn update_config_profile<T: AsRef<str>>(
     file_contents: &[T],
     config: Config,
 ) -> Result<Vec<String>, CliError> {
-    let (profile_start_line, profile_end_line) =
-        find_line_numbers_for_profile(file_contents, profile_name);
-    let mut updated_file_contents: Vec<String> = file_contents
-        .iter()
-        .map(|l| l.as_ref().to_string())
-        .collect();
-    for l in updated_file_contents
-        .iter_mut()
-        .take(profile_end_line)
-        .skip(profile_start_line)
-    {
-        *l = replace_config_value(l, &config)
+    let mut document = IniDocument::parse(file_contents);
+    document.set(profile_name, "cache", &config.cache);
+    document.set(profile_name, "ttl", &config.ttl.to_string());
+    match &config.endpoint {
+        Some(endpoint) => document.set(profile_name, "endpoint", endpoint),
+        None => {
+            document.remove(profile_name, "endpoint");
+        }
     }
-    Ok(updated_file_contents)
+    Ok(document.into_lines())
 }
 
-fn replace_credentials_value(existing_line: &str, credentials: &Credentials) -> String {
-    let line_with_updated_token = REGEX_CREDS_FILE_TOKEN_SETTING
-        .replace(existing_line, format!("token={}", credentials.token));
-
-    line_with_updated_token.to_string()
+/// Names of the profiles in the file, in order. Sections whose names start with a
+/// dot, such as `.momento_session`, hold CLI state rather than profiles and are skipped.
+pub fn list_profile_names(file_contents: &[impl AsRef<str>]) -> Vec<String> {
+    IniDocument::parse(file_contents)
+        .section_names()
+        .into_iter()
+        .filter(|name| !name.starts_with('.') && name != DEFAULTS_SECTION)
+        .collect()
 }
 
-fn replace_config_value(existing_line: &str, config: &Config) -> String {
-    let line_with_updated_cache =
-        REGEX_CONFIG_FILE_CACHE_SETTING.replace(existing_line, format!("cache={}", config.cache));
-
-    let line_with_updated_ttl = REGEX_CONFIG_FILE_TTL_SETTING.replace(
-        line_with_updated_cache.as_ref(),
-        format!("ttl={}", config.ttl),
-    );
+/// The sections whose `inherits` names `profile_name`.
+fn sections_inheriting_from(document: &IniDocument, profile_name: &str) -> Vec<String> {
+    document
+        .section_names()
+        .into_iter()
+        .filter(|section| document.get(section, INHERITS_KEY) == Some(profile_name))
+        .collect()
+}
 
-    line_with_updated_ttl.to_string()
+/// The profiles that take the settings they do not set from `profile_name`.
+pub fn profiles_inheriting_from(
+    profile_name: &str,
+    file_contents: &[impl AsRef<str>],
+) -> Vec<String> {
+    sections_inheriting_from(&IniDocument::parse(file_contents), profile_name)
 }
 
-pub fn does_profile_name_exist(file_contents: &[impl AsRef<str>], profile_name: &str) -> bool {
-    for line in file_contents.iter() {
-        let trimmed_line = line.as_ref().to_string().replace('\n', "");
-        if trimmed_line.eq(&format!("[{profile_name}]")) {
-            return true;
-        }
+/// Delete the profile, and the `inherits` of the profiles that inherited from it.
+pub fn delete_profile(profile_name: &str, file_contents: &[impl AsRef<str>]) -> Vec<String> {
+    let mut document = IniDocument::parse(file_contents);
+    document.remove_section(profile_name);
+    for child in sections_inheriting_from(&document, profile_name) {
+        document.remove(&child, INHERITS_KEY);
     }
-    false
+    document.into_lines()
 }
 
-fn find_line_numbers_for_profile(
-    file_contents: &[impl AsRef<str>],
+/// Rename the profile, and point the profiles that inherited from it at the new name.
+pub fn rename_profile(
     profile_name: &str,
-) -> (usize, usize) {
-    let mut counter = 0;
-    let mut start_line: usize = 0;
-    let mut end_line: usize = file_contents.len();
+    new_profile_name: &str,
+    file_contents: &[impl AsRef<str>],
+) -> Vec<String> {
+    let mut document = IniDocument::parse(file_contents);
+    document.rename_section(profile_name, new_profile_name);
+    for child in sections_inheriting_from(&document, profile_name) {
+        document.set(&child, INHERITS_KEY, new_profile_name);
+    }
+    document.into_lines()
+}
 
-    let mut lines_iter = file_contents.iter();
-    let expected_profile_line = format!("[{profile_name}]");
+pub fn copy_profile(
+    profile_name: &str,
+    new_profile_name: &str,
+    file_contents: &[impl AsRef<str>],
+) -> Vec<String> {
+    let mut document = IniDocument::parse(file_contents);
+    document.copy_section(profile_name, new_profile_name);
+    document.into_lines()
+}
 
-    loop {
-        let line = lines_iter.next();
-        match line {
-            None => {
-                break;
-            }
-            Some(l) => {
-                if *(l.as_ref()) == expected_profile_line {
-                    start_line = counter;
-                    break;
-                }
-            }
-        }
-        counter += 1;
-    }
+/// Set `key` in the given section, adding the key or the section if they do not exist yet.
+pub fn set_profile_value(
+    profile_name: &str,
+    key: &str,
+    value: &str,
+    file_contents: &[impl AsRef<str>],
+) -> Vec<String> {
+    let mut document = IniDocument::parse(file_contents);
+    document.set(profile_name, key, value);
+    document.into_lines()
+}
 
-    loop {
-        counter += 1;
-        let line = lines_iter.next();
-        match line {
-            None => {
-                break;
-            }
-            Some(l) => {
-                if is_profile_header_line(l.as_ref()) {
-                    end_line = counter;
-                    break;
-                }
-            }
-        }
+/// Remove `key` from the given section, and the section too if nothing else is left in it.
+pub fn remove_profile_value(
+    profile_name: &str,
+    key: &str,
+    file_contents: &[impl AsRef<str>],
+) -> Vec<String> {
+    let mut document = IniDocument::parse(file_contents);
+    if document.remove(profile_name, key) && document.is_section_empty(profile_name) {
+        document.remove_section(profile_name);
     }
-
-    (start_line, end_line)
+    document.into_lines()
 }
 
-fn is_profile_header_line(line: &str) -> bool {
-    REGEX_PROFILE_HEADER.is_match(line)
+pub fn does_profile_name_exist(file_contents: &[impl AsRef<str>], profile_name: &str) -> bool {
+    IniDocument::parse(file_contents).has_section(profile_name)
 }
 
 #[cfg(test)]
 mod tests {
     use crate::config::{Config, Credentials};
     use crate::utils::ini_config::{
-        create_new_config_profile, create_new_credentials_profile, update_config_profile,
-        update_credentials_profile,
+        copy_profile, create_new_config_profile, create_new_credentials_profile, delete_profile,
+        list_profile_names, profiles_inheriting_from, remove_profile_value, rename_profile,
+        set_profile_value, update_config_profile, update_credentials_profile,
+        update_generated_token_profile, IniDocument,
     };
 
     fn test_file_content(untrimmed_file_contents: &str) -> String {
@@ -170,7 +417,7 @@ mod tests {
         let profile_text = create_new_credentials_profile(
             "default",
             Credentials {
-                token: "awesome-token".to_string(),
+                token: "awesome-token".into(),
             },
         )
         .join("\n");
@@ -190,6 +437,8 @@ token=awesome-token
             Config {
                 cache: "awesome-cache".to_string(),
                 ttl: 90210,
+                endpoint: None,
+                ..Default::default()
             },
         )
         .join("\n");
@@ -213,7 +462,7 @@ token=invalidtoken
         );
         let file_lines: Vec<&str> = file_contents.split('\n').collect();
         let creds = Credentials {
-            token: "newtoken".to_string(),
+            token: "newtoken".into(),
         };
         let result = update_credentials_profile("default", &file_lines, creds);
         assert!(result.is_ok());
@@ -229,6 +478,39 @@ token=newtoken
         assert_eq!(expected_content, new_content);
     }
 
+    #[test]
+    fn update_generated_token_profile_keeps_the_previous_token() {
+        let file_contents = test_file_content(
+            "
+# rotated by the deploy script
+[default]
+token=oldtoken
+token_valid_until=2024-01-01T00:00:00+00:00
+        ",
+        );
+        let file_lines: Vec<&str> = file_contents.split('\n').collect();
+        let new_content = update_generated_token_profile(
+            "default",
+            &file_lines,
+            &"newtoken".into(),
+            &"refreshtoken".into(),
+            None,
+        )
+        .join("\n");
+
+        let expected_content = test_file_content(
+            "
+# rotated by the deploy script
+[default]
+token=newtoken
+previous_token=oldtoken
+refresh_token=refreshtoken
+        ",
+        );
+
+        assert_eq!(expected_content, new_content);
+    }
+
     #[test]
     fn update_credentials_profile_values_one_existing_profile_with_empty_token() {
         let file_contents = test_file_content(
@@ -239,7 +521,7 @@ token=
         );
         let file_lines: Vec<&str> = file_contents.split('\n').collect();
         let creds = Credentials {
-            token: "newtoken".to_string(),
+            token: "newtoken".into(),
         };
         let result = update_credentials_profile("default", &file_lines, creds);
         assert!(result.is_ok());
@@ -271,7 +553,7 @@ token=spicytoken
         );
         let file_lines: Vec<&str> = file_contents.split('\n').collect();
         let creds = Credentials {
-            token: "newtoken".to_string(),
+            token: "newtoken".into(),
         };
         let result = update_credentials_profile("default", &file_lines, creds);
         assert!(result.is_ok());
@@ -306,6 +588,8 @@ ttl=600
         let config = Config {
             cache: "new-cache".to_string(),
             ttl: 90210,
+            endpoint: None,
+            ..Default::default()
         };
         let result = update_config_profile("default", &file_lines, config);
         assert!(result.is_ok());
@@ -343,6 +627,8 @@ ttl=600
         let config = Config {
             cache: "new-cache".to_string(),
             ttl: 90210,
+            endpoint: None,
+            ..Default::default()
         };
         let result = update_config_profile("default", &file_lines, config);
         assert!(result.is_ok());
@@ -366,4 +652,427 @@ ttl=600
 
         assert_eq!(expected_content, new_content);
     }
+
+    fn three_profiles() -> String {
+        test_file_content(
+            "
+[taco]
+token=invalidtoken
+
+[default]
+token=anotherinvalidtoken
+
+[habanero]
+token=spicytoken
+        ",
+        )
+    }
+
+    #[test]
+    fn list_profile_names_skips_cli_state_sections() {
+        let file_contents = test_file_content(
+            "
+[default]
+token=anotherinvalidtoken
+
+[.momento_session]
+token=sessiontoken
+
+[defaults]
+ttl=600
+
+[habanero]
+token=spicytoken
+        ",
+        );
+        let file_lines: Vec<&str> = file_contents.split('\n').collect();
+        assert_eq!(
+            vec!["default".to_string(), "habanero".to_string()],
+            list_profile_names(&file_lines)
+        );
+    }
+
+    #[test]
+    fn delete_profile_in_the_middle() {
+        let file_contents = three_profiles();
+        let file_lines: Vec<&str> = file_contents.split('\n').collect();
+        let new_content = delete_profile("default", &file_lines).join("\n");
+
+        let expected_content = test_file_content(
+            "
+[taco]
+token=invalidtoken
+
+[habanero]
+token=spicytoken
+        ",
+        );
+
+        assert_eq!(expected_content, new_content);
+    }
+
+    #[test]
+    fn delete_missing_profile_changes_nothing() {
+        let file_contents = three_profiles();
+        let file_lines: Vec<&str> = file_contents.split('\n').collect();
+        assert_eq!(
+            file_contents,
+            delete_profile("missing", &file_lines).join("\n")
+        );
+    }
+
+    #[test]
+    fn rename_profile_only_renames_the_header() {
+        let file_contents = three_profiles();
+        let file_lines: Vec<&str> = file_contents.split('\n').collect();
+        let new_content = rename_profile("default", "prod", &file_lines).join("\n");
+
+        let expected_content = test_file_content(
+            "
+[taco]
+token=invalidtoken
+
+[prod]
+token=anotherinvalidtoken
+
+[habanero]
+token=spicytoken
+        ",
+        );
+
+        assert_eq!(expected_content, new_content);
+    }
+
+    #[test]
+    fn rename_and_delete_profile_update_inherits() {
+        let file_contents = test_file_content(
+            "
+[base]
+cache=shared
+
+[team-a]
+inherits=base
+cache=team-a
+
+[team-b]
+inherits = base
+
+[other]
+inherits=team-a
+        ",
+        );
+        let file_lines: Vec<&str> = file_contents.split('\n').collect();
+        assert_eq!(
+            vec!["team-a".to_string(), "team-b".to_string()],
+            profiles_inheriting_from("base", &file_lines)
+        );
+
+        let renamed = rename_profile("base", "shared", &file_lines).join("\n");
+        assert_eq!(
+            test_file_content(
+                "
+[shared]
+cache=shared
+
+[team-a]
+inherits=shared
+cache=team-a
+
+[team-b]
+inherits = shared
+
+[other]
+inherits=team-a
+        ",
+            ),
+            renamed
+        );
+
+        let deleted = delete_profile("base", &file_lines).join("\n");
+        assert_eq!(
+            test_file_content(
+                "
+[team-a]
+cache=team-a
+
+[team-b]
+
+[other]
+inherits=team-a
+        ",
+            ),
+            deleted
+        );
+    }
+
+    #[test]
+    fn copy_profile_appends_new_profile() {
+        let file_contents = three_profiles();
+        let file_lines: Vec<&str> = file_contents.split('\n').collect();
+        let new_content = copy_profile("default", "prod", &file_lines).join("\n");
+
+        let expected_content = format!("{}\n[prod]\ntoken=anotherinvalidtoken", three_profiles());
+
+        assert_eq!(expected_content, new_content);
+    }
+
+    #[test]
+    fn set_and_remove_profile_value() {
+        let file_contents = test_file_content(
+            "
+[default]
+cache=default-cache
+ttl=600
+        ",
+        );
+        let file_lines: Vec<&str> = file_contents.split('\n').collect();
+        let added = set_profile_value(".momento_settings", "current_profile", "prod", &file_lines);
+        assert_eq!(
+            "[default]\ncache=default-cache\nttl=600\n\n[.momento_settings]\ncurrent_profile=prod",
+            added.join("\n")
+        );
+
+        let updated = set_profile_value("default", "ttl", "90210", &file_lines);
+        assert_eq!(
+            test_file_content(
+                "
+[default]
+cache=default-cache
+ttl=90210
+        "
+            ),
+            updated.join("\n")
+        );
+
+        let removed = remove_profile_value("default", "cache", &file_lines);
+        assert_eq!(
+            test_file_content(
+                "
+[default]
+ttl=600
+        "
+            ),
+            removed.join("\n")
+        );
+
+        let emptied = remove_profile_value(".momento_settings", "current_profile", &added);
+        assert_eq!(file_contents.trim_end(), emptied.join("\n").trim_end());
+    }
+
+    #[test]
+    fn create_new_config_profile_with_endpoint() {
+        let profile_text = create_new_config_profile(
+            "default",
+            Config {
+                cache: "awesome-cache".to_string(),
+                ttl: 90210,
+                endpoint: Some("cell-us-east-1-1.prod.a.momentohq.com".to_string()),
+                ..Default::default()
+            },
+        )
+        .join("\n");
+        let expected_text = test_file_content(
+            "
+[default]
+cache=awesome-cache
+ttl=90210
+endpoint=cell-us-east-1-1.prod.a.momentohq.com
+        ",
+        );
+        assert_eq!(expected_text.trim(), profile_text)
+    }
+
+    #[test]
+    fn update_profile_values_config_adds_and_removes_endpoint() {
+        let file_contents = test_file_content(
+            "
+[default]
+cache=default-cache
+ttl=600
+
+[habanero]
+cache=spicy-cache
+ttl=600
+        ",
+        );
+        let file_lines: Vec<&str> = file_contents.split('\n').collect();
+        let config = Config {
+            cache: "new-cache".to_string(),
+            ttl: 90210,
+            endpoint: Some("cell-1.example.com".to_string()),
+            ..Default::default()
+        };
+        let with_endpoint = update_config_profile("default", &file_lines, config).expect("d'oh");
+
+        let expected_content = test_file_content(
+            "
+[default]
+cache=new-cache
+ttl=90210
+endpoint=cell-1.example.com
+
+[habanero]
+cache=spicy-cache
+ttl=600
+        ",
+        );
+        assert_eq!(expected_content, with_endpoint.join("\n"));
+
+        let config = Config {
+            cache: "new-cache".to_string(),
+            ttl: 90210,
+            endpoint: None,
+            ..Default::default()
+        };
+        let without_endpoint =
+            update_config_profile("default", &with_endpoint, config).expect("d'oh");
+
+        let expected_content = test_file_content(
+            "
+[default]
+cache=new-cache
+ttl=90210
+
+[habanero]
+cache=spicy-cache
+ttl=600
+        ",
+        );
+        assert_eq!(expected_content, without_endpoint.join("\n"));
+    }
+
+    #[test]
+    fn update_credentials_profile_token_with_base64_characters() {
+        let file_contents = test_file_content(
+            "
+[default]
+token=old+token/with==
+        ",
+        );
+        let file_lines: Vec<&str> = file_contents.split('\n').collect();
+        let creds = Credentials {
+            token: "new+token/with=".into(),
+        };
+        let new_content = update_credentials_profile("default", &file_lines, creds)
+            .expect("d'oh")
+            .join("\n");
+
+        let expected_content = test_file_content(
+            "
+[default]
+token=new+token/with=
+        ",
+        );
+
+        assert_eq!(expected_content, new_content);
+    }
+
+    #[test]
+    fn update_profile_values_config_preserves_comments_and_unknown_keys() {
+        let file_contents = test_file_content(
+            "
+# managed by hand
+[ default ]
+; the cache for the demo
+cache = default-cache
+color=blue
+ttl=600
+
+[habanero]
+cache=spicy-cache
+ttl=600
+        ",
+        );
+        let file_lines: Vec<&str> = file_contents.split('\n').collect();
+        let config = Config {
+            cache: "new-cache".to_string(),
+            ttl: 90210,
+            endpoint: None,
+            ..Default::default()
+        };
+        let new_content = update_config_profile("default", &file_lines, config)
+            .expect("d'oh")
+            .join("\n");
+
+        let expected_content = test_file_content(
+            "
+# managed by hand
+[ default ]
+; the cache for the demo
+cache = new-cache
+color=blue
+ttl=90210
+
+[habanero]
+cache=spicy-cache
+ttl=600
+        ",
+        );
+
+        assert_eq!(expected_content, new_content);
+    }
+
+    #[test]
+    fn ini_document_round_trips_unchanged() {
+        let file_lines = vec![
+            "; leading comment",
+            "",
+            "[default]",
+            "token = some-token   ",
+            "# trailing comment",
+            "not a key value pair",
+            "",
+        ];
+        assert_eq!(file_lines, IniDocument::parse(&file_lines).into_lines());
+    }
+
+    #[test]
+    fn ini_document_adds_updates_and_removes_arbitrary_keys() {
+        let file_contents = test_file_content(
+            "
+[default]
+token=some-token
+
+[habanero]
+token=spicytoken
+        ",
+        );
+        let file_lines: Vec<&str> = file_contents.split('\n').collect();
+        let mut document = IniDocument::parse(&file_lines);
+        assert_eq!(Some("some-token"), document.get("default", "token"));
+        assert_eq!(None, document.get("default", "region"));
+        assert_eq!(None, document.get("missing", "token"));
+
+        document.set("default", "region", "us-west-2");
+        document.set("habanero", "token", "hottertoken");
+        document.set("taco", "filling", "al pastor");
+        assert!(document.remove("default", "token"));
+        assert!(!document.remove("default", "token"));
+        assert_eq!(Some("us-west-2"), document.get("default", "region"));
+        assert_eq!(
+            vec![
+                "default".to_string(),
+                "habanero".to_string(),
+                "taco".to_string()
+            ],
+            document.section_names()
+        );
+
+        let expected_content = test_file_content(
+            "
+[default]
+region=us-west-2
+
+[habanero]
+token=hottertoken
+
+[taco]
+filling=al pastor
+        ",
+        );
+        assert_eq!(
+            expected_content.trim_end(),
+            document.into_lines().join("\n")
+        );
+    }
 }
@@ -1,5 +1,12 @@
+pub mod api_key;
 pub mod client;
 pub mod console;
+pub mod credential_process;
+pub mod encryption;
 pub mod file;
+pub mod inheritance;
 pub mod ini_config;
+pub mod process;
+pub mod secret;
+pub mod toml_config;
 pub mod user;
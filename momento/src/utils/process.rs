@@ -0,0 +1,16 @@
+use tokio::process::Command;
+
+/// A command that runs `command` through the platform shell.
+#[cfg(not(target_os = "windows"))]
+pub fn shell_command(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(target_os = "windows")]
+pub fn shell_command(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
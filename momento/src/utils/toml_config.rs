@@ -0,0 +1,400 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{ensure_config_key, parse_config_number, validate_config_value, Config, OutputFormat},
+    error::CliError,
+    utils::{
+        file::{get_toml_config_file_path, write_to_file},
+        inheritance::{DEFAULTS_SECTION, INHERITS_KEY},
+        ini_config::IniDocument,
+        secret::SecretString,
+    },
+};
+
+/// The contents of `config.toml`, which replaces the `config` and `credentials`
+/// INI files once they have been migrated.
+#[derive(Deserialize, Serialize, Default, Debug, PartialEq)]
+pub struct TomlConfig {
+    /// The profile used when `--profile` is not passed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub current_profile: Option<String>,
+    /// Settings every profile falls back to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub defaults: Option<ProfileSettings>,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, ProfileSettings>,
+}
+
+impl TomlConfig {
+    /// A profile, or the defaults for `defaults`.
+    pub fn section(&self, name: &str) -> Option<&ProfileSettings> {
+        if name == DEFAULTS_SECTION {
+            self.defaults.as_ref()
+        } else {
+            self.profiles.get(name)
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Default, Debug, PartialEq, Clone)]
+pub struct ProfileSettings {
+    /// The profile to take the settings this profile does not set from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inherits: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<SecretString>,
+    /// Set when the token was generated by the CLI, with the expiry of the token.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<SecretString>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_valid_until: Option<String>,
+    /// The token that the last generated token replaced.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_token: Option<SecretString>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credential_process: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub endpoint: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<OutputFormat>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_timeout_seconds: Option<u64>,
+}
+
+impl ProfileSettings {
+    /// The value of a `Config` setting, formatted like it is in the INI config file.
+    pub fn get_setting(&self, key: &str) -> Option<String> {
+        match key {
+            "cache" => self.cache.clone(),
+            "ttl" => self.ttl.map(|ttl| ttl.to_string()),
+            "endpoint" => self.endpoint.clone(),
+            "output" => self.output.map(|output| output.to_string()),
+            "request_timeout_seconds" => self.request_timeout_seconds.map(|t| t.to_string()),
+            _ => None,
+        }
+    }
+
+    pub fn set_setting(&mut self, key: &str, value: &str) -> Result<(), CliError> {
+        validate_config_value(key, value)?;
+        match key {
+            "cache" => self.cache = Some(value.to_string()),
+            "ttl" => self.ttl = Some(parse_config_number(key, value)?),
+            "endpoint" => self.endpoint = Some(value.to_string()),
+            "output" => self.output = Some(value.parse()?),
+            "request_timeout_seconds" => {
+                self.request_timeout_seconds = Some(parse_config_number(key, value)?)
+            }
+            _ => ensure_config_key(key)?,
+        }
+        Ok(())
+    }
+
+    pub fn unset_setting(&mut self, key: &str) -> Result<(), CliError> {
+        match key {
+            "cache" => self.cache = None,
+            "ttl" => self.ttl = None,
+            "endpoint" => self.endpoint = None,
+            "output" => self.output = None,
+            "request_timeout_seconds" => self.request_timeout_seconds = None,
+            _ => ensure_config_key(key)?,
+        }
+        Ok(())
+    }
+
+    /// Store a token generated for this profile, keeping the token it replaces as
+    /// `previous_token`.
+    pub fn set_generated_token(
+        &mut self,
+        token: SecretString,
+        refresh_token: SecretString,
+        valid_until: Option<String>,
+    ) {
+        if let Some(previous) = self
+            .token
+            .take()
+            .filter(|previous| !previous.is_empty() && *previous != token)
+        {
+            self.previous_token = Some(previous);
+        }
+        self.token = Some(token);
+        self.refresh_token = Some(refresh_token);
+        self.token_valid_until = valid_until;
+    }
+
+    pub fn update_config(&mut self, config: Config) {
+        self.cache = Some(config.cache);
+        self.ttl = Some(config.ttl);
+        self.endpoint = config.endpoint;
+    }
+}
+
+/// Read `config.toml`, or `None` if the INI files have not been migrated yet.
+pub async fn read_toml_config() -> Result<Option<TomlConfig>, CliError> {
+    let path = get_toml_config_file_path()?;
+    if !Path::new(&path).exists() {
+        return Ok(None);
+    }
+    let contents = tokio::fs::read_to_string(&path).await?;
+    let config = toml::from_str(&contents).map_err(|e| CliError {
+        msg: format!("failed to parse {}: {e}", path.display()),
+    })?;
+    Ok(Some(config))
+}
+
+/// The settings of `profile` from `config.toml`, if the file exists and has that profile.
+pub async fn get_toml_profile_settings(profile: &str) -> Result<Option<ProfileSettings>, CliError> {
+    Ok(read_toml_config()
+        .await?
+        .and_then(|config| config.profiles.get(profile).cloned()))
+}
+
+pub async fn write_toml_config(config: &TomlConfig) -> Result<(), CliError> {
+    let path = get_toml_config_file_path()?;
+    let contents = toml::to_string_pretty(config).map_err(|e| CliError {
+        msg: format!("failed to serialize {}: {e}", path.display()),
+    })?;
+    write_to_file(&path, contents).await
+}
+
+/// Build a `config.toml` from the lines of the legacy `config` and `credentials` files.
+pub fn migrate_ini_files(
+    config_lines: &[impl AsRef<str>],
+    credentials_lines: &[impl AsRef<str>],
+) -> Result<TomlConfig, CliError> {
+    let configs = IniDocument::parse(config_lines);
+    let credentials = IniDocument::parse(credentials_lines);
+    let mut migrated = TomlConfig {
+        current_profile: configs
+            .get(".momento_settings", "current_profile")
+            .map(|profile| profile.to_string()),
+        defaults: None,
+        profiles: BTreeMap::new(),
+    };
+    let profile_names = configs
+        .section_names()
+        .into_iter()
+        .chain(credentials.section_names())
+        // Dotted sections hold CLI state such as login sessions, not profiles.
+        .filter(|name| !name.starts_with('.'));
+    for name in profile_names {
+        let parse_number = |key: &str| -> Result<Option<u64>, CliError> {
+            configs
+                .get(&name, key)
+                .map(|value| {
+                    parse_config_number(key, value).map_err(|e| CliError {
+                        msg: format!("profile {name}: {}", e.msg),
+                    })
+                })
+                .transpose()
+        };
+        let settings = ProfileSettings {
+            inherits: configs.get(&name, INHERITS_KEY).map(|i| i.to_string()),
+            token: credentials.get(&name, "token").map(SecretString::from),
+            refresh_token: credentials
+                .get(&name, "refresh_token")
+                .map(SecretString::from),
+            token_valid_until: credentials
+                .get(&name, "token_valid_until")
+                .map(|v| v.to_string()),
+            previous_token: credentials
+                .get(&name, "previous_token")
+                .map(SecretString::from),
+            credential_process: credentials
+                .get(&name, "credential_process")
+                .map(|c| c.to_string()),
+            cache: configs.get(&name, "cache").map(|c| c.to_string()),
+            ttl: parse_number("ttl")?,
+            endpoint: configs.get(&name, "endpoint").map(|e| e.to_string()),
+            output: configs
+                .get(&name, "output")
+                .map(|o| o.parse())
+                .transpose()?,
+            request_timeout_seconds: parse_number("request_timeout_seconds")?,
+        };
+        if name == DEFAULTS_SECTION {
+            migrated.defaults = Some(settings);
+        } else {
+            migrated.profiles.insert(name, settings);
+        }
+    }
+    Ok(migrated)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::OutputFormat;
+    use crate::utils::toml_config::{migrate_ini_files, ProfileSettings, TomlConfig};
+
+    fn lines(contents: &str) -> Vec<String> {
+        contents.trim().split('\n').map(|l| l.to_string()).collect()
+    }
+
+    #[test]
+    fn migrate_ini_files_merges_config_and_credentials() {
+        let config = lines(
+            "
+[default]
+cache=default-cache
+ttl=600
+
+[prod]
+cache=prod-cache
+ttl=90
+endpoint=cell-1.example.com
+output=json
+
+[.momento_settings]
+current_profile=prod
+        ",
+        );
+        let credentials = lines(
+            "
+[default]
+token=default-token
+
+[prod]
+credential_process=vault read momento
+
+[.momento_session]
+token=session-token
+valid_until=0
+        ",
+        );
+        let migrated = migrate_ini_files(&config, &credentials).expect("d'oh");
+
+        assert_eq!(Some("prod".to_string()), migrated.current_profile);
+        assert_eq!(2, migrated.profiles.len());
+        assert_eq!(
+            ProfileSettings {
+                token: Some("default-token".into()),
+                cache: Some("default-cache".to_string()),
+                ttl: Some(600),
+                ..Default::default()
+            },
+            migrated.profiles["default"]
+        );
+        assert_eq!(
+            ProfileSettings {
+                credential_process: Some("vault read momento".to_string()),
+                cache: Some("prod-cache".to_string()),
+                ttl: Some(90),
+                endpoint: Some("cell-1.example.com".to_string()),
+                output: Some(OutputFormat::Json),
+                ..Default::default()
+            },
+            migrated.profiles["prod"]
+        );
+    }
+
+    #[test]
+    fn set_generated_token_keeps_the_previous_token() {
+        let mut settings = ProfileSettings {
+            token: Some("old-token".into()),
+            token_valid_until: Some("2024-01-01T00:00:00+00:00".to_string()),
+            ..Default::default()
+        };
+        settings.set_generated_token("new-token".into(), "refresh-token".into(), None);
+        assert_eq!(
+            ProfileSettings {
+                token: Some("new-token".into()),
+                refresh_token: Some("refresh-token".into()),
+                previous_token: Some("old-token".into()),
+                ..Default::default()
+            },
+            settings
+        );
+    }
+
+    #[test]
+    fn migrate_ini_files_rejects_invalid_numbers() {
+        let config = lines(
+            "
+[default]
+cache=default-cache
+ttl=ten minutes
+        ",
+        );
+        assert!(migrate_ini_files(&config, &Vec::<String>::new()).is_err());
+    }
+
+    #[test]
+    fn toml_config_round_trips() {
+        let migrated = migrate_ini_files(
+            &lines(
+                "
+[default]
+cache=default-cache
+ttl=600
+request_timeout_seconds=15
+        ",
+            ),
+            &lines(
+                "
+[default]
+token=default-token
+        ",
+            ),
+        )
+        .expect("d'oh");
+        let serialized = toml::to_string_pretty(&migrated).expect("d'oh");
+        assert_eq!(
+            "[profiles.default]\ntoken = \"default-token\"\ncache = \"default-cache\"\nttl = 600\nrequest_timeout_seconds = 15\n",
+            serialized
+        );
+        let parsed: TomlConfig = toml::from_str(&serialized).expect("d'oh");
+        assert_eq!(migrated, parsed);
+    }
+
+    #[test]
+    fn profile_settings_get_set_and_unset_by_key() {
+        let mut settings = ProfileSettings::default();
+        settings.set_setting("ttl", "90").expect("d'oh");
+        settings.set_setting("output", "json").expect("d'oh");
+        assert!(settings.set_setting("ttl", "ninety").is_err());
+        assert!(settings.set_setting("token", "secret").is_err());
+        assert_eq!(Some(90), settings.ttl);
+        assert_eq!(Some("json".to_string()), settings.get_setting("output"));
+
+        settings.unset_setting("output").expect("d'oh");
+        assert_eq!(None, settings.get_setting("output"));
+        assert_eq!(Some("90".to_string()), settings.get_setting("ttl"));
+    }
+
+    #[test]
+    fn migrate_ini_files_keeps_defaults_and_inheritance() {
+        let config = lines(
+            "
+[defaults]
+ttl=600
+
+[base]
+cache=base-cache
+
+[prod]
+inherits=base
+        ",
+        );
+        let migrated = migrate_ini_files(&config, &Vec::<String>::new()).expect("d'oh");
+
+        assert_eq!(
+            Some(ProfileSettings {
+                ttl: Some(600),
+                ..Default::default()
+            }),
+            migrated.defaults
+        );
+        assert_eq!(
+            vec!["base", "prod"],
+            migrated.profiles.keys().collect::<Vec<_>>()
+        );
+        assert_eq!(Some("base".to_string()), migrated.profiles["prod"].inherits);
+        assert_eq!(Some(600), migrated.section("defaults").and_then(|d| d.ttl));
+    }
+}
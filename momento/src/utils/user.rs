@@ -1,98 +1,425 @@
-use chrono::{Duration, TimeZone, Utc};
-use configparser::ini::Ini;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
 
 use crate::{
-    config::{Config, Credentials},
+    config::{
+        Config, Credentials, ENV_VAR_NAME_MOMENTO_API_KEY, ENV_VAR_NAME_MOMENTO_AUTH_TOKEN,
+        ENV_VAR_NAME_MOMENTO_CACHE, ENV_VAR_NAME_MOMENTO_ENDPOINT, ENV_VAR_NAME_MOMENTO_PROFILE,
+        ENV_VAR_NAME_MOMENTO_TTL,
+    },
     error::CliError,
-    utils::file::{get_config_file_path, get_credentials_file_path, read_ini_file},
+    utils::file::{
+        get_config_file_path, get_credentials_file_path, get_sessions_file_path, read_ini_file,
+    },
 };
 
-use crate::utils::file::write_to_file;
-
-fn get_session_token(credentials: &Ini) -> Option<String> {
-    let session_token = credentials.get(".momento_session", "token");
-    if session_token.is_some() {
-        let expiry = credentials
-            .get(".momento_session", "valid_until")
-            .map(|s| s.parse::<i64>().unwrap_or(0))
-            .map(|expiry_timestamp| Utc.timestamp_opt(expiry_timestamp, 0).single());
-        if let Some(Some(expiry_timestamp)) = expiry {
-            if Utc::now() + Duration::seconds(10) < expiry_timestamp {
-                let expiring = expiry_timestamp - Utc::now();
-                log::debug!("Found user session expiring in {}m", expiring.num_minutes());
-                return session_token;
-            }
-            log::debug!("Token already expired at: {}", expiry_timestamp);
-        } else {
+use crate::utils::client::init_client_settings;
+use crate::utils::credential_process::get_credential_process_token;
+use crate::utils::encryption::{read_protected_lines, write_protected_lines};
+use crate::utils::file::{
+    lines_to_file_content, lock_config_dir, read_file_lines_if_exists, write_to_file,
+};
+use crate::utils::inheritance::{
+    config_from_settings, inheritance_chain, resolve_settings, ResolvedSetting, INHERITS_KEY,
+};
+use crate::utils::ini_config::{remove_profile_value, set_profile_value, IniDocument};
+use crate::utils::secret::SecretString;
+use crate::utils::toml_config::{
+    get_toml_profile_settings, read_toml_config, write_toml_config, ProfileSettings,
+};
+
+const DEFAULT_PROFILE_NAME: &str = "default";
+/// The config file section holding CLI settings that are not tied to a profile.
+const SETTINGS_SECTION: &str = ".momento_settings";
+const CURRENT_PROFILE_KEY: &str = "current_profile";
+
+/// The legacy login session shared by every profile, which was kept in the credentials
+/// file. It is no longer read, only removed.
+const LEGACY_SESSION_SECTION: &str = ".momento_session";
+
+/// The sessions file section holding the login session of `profile`.
+pub fn session_section(profile: &str) -> String {
+    format!("{LEGACY_SESSION_SECTION}.{profile}")
+}
+
+/// A token from `momento preview login`, which is used instead of the profile's own token
+/// until it expires.
+pub struct Session {
+    pub token: SecretString,
+    pub valid_until: DateTime<Utc>,
+}
+
+impl Session {
+    /// Like credential_process tokens, a session is not used when it is about to expire.
+    pub fn is_valid(&self) -> bool {
+        Utc::now() + Duration::seconds(10) < self.valid_until
+    }
+}
+
+fn read_session(sessions: &IniDocument, profile: &str) -> Option<Session> {
+    let section = session_section(profile);
+    let token = sessions.get(&section, "token")?;
+    let valid_until = sessions
+        .get(&section, "valid_until")
+        .and_then(|s| s.parse::<i64>().ok())
+        .and_then(|expiry_timestamp| Utc.timestamp_opt(expiry_timestamp, 0).single());
+    match valid_until {
+        Some(valid_until) => Some(Session {
+            token: token.into(),
+            valid_until,
+        }),
+        None => {
+            log::debug!("{section} is missing the expiry time. Skipping this session...");
+            None
+        }
+    }
+}
+
+/// The login session of `profile`, whether or not it has expired.
+pub async fn get_session(profile: &str) -> Result<Option<Session>, CliError> {
+    let path = get_sessions_file_path()?;
+    let sessions = IniDocument::parse(&read_file_lines_if_exists(&path).await?);
+    Ok(read_session(&sessions, profile))
+}
+
+async fn get_session_token(profile: &str) -> Option<SecretString> {
+    match get_session(profile).await.ok().flatten() {
+        Some(session) if session.is_valid() => {
+            let expiring = session.valid_until - Utc::now();
             log::debug!(
-                ".momento_session profile is missing the expiry time. Skipping this session..."
+                "Using the login session of profile {profile}, expiring in {}m",
+                expiring.num_minutes()
             );
+            Some(session.token)
+        }
+        Some(session) => {
+            log::debug!(
+                "The login session of profile {profile} expired at {}",
+                session.valid_until
+            );
+            None
+        }
+        None => {
+            log::debug!("No login session found for profile {profile}");
+            None
         }
     }
-    log::debug!("No session found in .momento_session profile...");
-    None
 }
 
-fn set_session_token(credentials: &mut Ini, session_token: Option<String>, valid_for_seconds: u32) {
+fn set_session_token(
+    sessions: &mut IniDocument,
+    profile: &str,
+    session_token: Option<SecretString>,
+    valid_for_seconds: u32,
+) {
+    let section = session_section(profile);
     let expiry_time = Utc::now() + Duration::seconds(valid_for_seconds.into());
-    credentials.set(".momento_session", "token", session_token);
-    credentials.set(
-        ".momento_session",
+    match session_token {
+        Some(token) => sessions.set(&section, "token", token.expose_secret()),
+        None => {
+            sessions.remove(&section, "token");
+        }
+    }
+    sessions.set(
+        &section,
         "valid_until",
-        Some(expiry_time.timestamp().to_string()),
+        &expiry_time.timestamp().to_string(),
     );
 }
 
 pub async fn clobber_session_token(
-    session_token: Option<String>,
+    profile: &str,
+    session_token: Option<SecretString>,
     valid_for_seconds: u32,
 ) -> Result<(), CliError> {
-    let mut credentials_file = read_credentials().await?;
-    set_session_token(&mut credentials_file, session_token, valid_for_seconds);
-    // TODO
-    // TODO this is silly, should change write_to_file to take a String or have one fn for vec and one for string
-    // TODO
-    write_to_file(
+    let _lock = lock_config_dir().await?;
+    let path = get_sessions_file_path()?;
+    let mut sessions_file = IniDocument::parse(&read_file_lines_if_exists(&path).await?);
+    set_session_token(
+        &mut sessions_file,
+        profile,
+        session_token,
+        valid_for_seconds,
+    );
+    write_to_file(&path, lines_to_file_content(sessions_file.into_lines())).await
+}
+
+/// Remove `sections` from the INI file at `path`, returning whether any of them were there.
+async fn remove_sections(path: &Path, sections: &[String]) -> Result<bool, CliError> {
+    let mut file = IniDocument::parse(&read_protected_lines(path).await?);
+    let mut removed = false;
+    for section in sections {
+        if file.has_section(section) {
+            file.remove_section(section);
+            removed = true;
+        }
+    }
+    if removed {
+        write_protected_lines(path, file.into_lines()).await?;
+    }
+    Ok(removed)
+}
+
+/// Remove the login session of `profile`, along with the sessions that older versions of
+/// the CLI kept in the credentials file. Returns whether there was a session to remove.
+pub async fn remove_session(profile: &str) -> Result<bool, CliError> {
+    let _lock = lock_config_dir().await?;
+    let section = session_section(profile);
+    let removed =
+        remove_sections(&get_sessions_file_path()?, std::slice::from_ref(&section)).await?;
+    let removed_legacy = remove_sections(
         &get_credentials_file_path()?,
-        credentials_file
-            .writes()
-            .split_inclusive('\n')
-            .map(|line| line.to_string())
-            .collect(),
+        &[section, LEGACY_SESSION_SECTION.to_string()],
     )
     .await?;
-    Ok(())
+    Ok(removed || removed_legacy)
+}
+
+/// The profile selected with `momento profile use`, if any.
+pub async fn get_current_profile() -> Option<String> {
+    if let Ok(Some(toml_config)) = read_toml_config().await {
+        return toml_config.current_profile;
+    }
+    let path = get_config_file_path().ok()?;
+    let configs = IniDocument::parse(&read_file_lines_if_exists(&path).await.ok()?);
+    configs
+        .get(SETTINGS_SECTION, CURRENT_PROFILE_KEY)
+        .filter(|profile| !profile.is_empty())
+        .map(|profile| profile.to_string())
+}
+
+/// Select the profile used when `--profile` is not passed, or clear the selection.
+pub async fn set_current_profile(profile: Option<&str>) -> Result<(), CliError> {
+    let _lock = lock_config_dir().await?;
+    if let Some(mut toml_config) = read_toml_config().await? {
+        toml_config.current_profile = profile.map(|p| p.to_string());
+        return write_toml_config(&toml_config).await;
+    }
+    let path = get_config_file_path()?;
+    let file_contents = read_file_lines_if_exists(&path).await?;
+    let updated_file_contents = match profile {
+        Some(profile) => set_profile_value(
+            SETTINGS_SECTION,
+            CURRENT_PROFILE_KEY,
+            profile,
+            &file_contents,
+        ),
+        None => remove_profile_value(SETTINGS_SECTION, CURRENT_PROFILE_KEY, &file_contents),
+    };
+    write_to_file(&path, lines_to_file_content(updated_file_contents)).await
+}
+
+/// An environment variable override, ignoring variables that are set but empty.
+fn env_override(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+/// The `--profile` flag if it was passed, then `MOMENTO_PROFILE`, then the current
+/// profile, then `default`.
+pub async fn resolve_profile_name(profile_flag: Option<String>) -> String {
+    if let Some(profile) = profile_flag {
+        log::debug!("Using profile {profile} from --profile");
+        return profile;
+    }
+    if let Some(profile) = env_override(ENV_VAR_NAME_MOMENTO_PROFILE) {
+        log::debug!("Using profile {profile} from {ENV_VAR_NAME_MOMENTO_PROFILE}");
+        return profile;
+    }
+    match get_current_profile().await {
+        Some(profile) => {
+            log::debug!("Using the current profile {profile}");
+            profile
+        }
+        None => DEFAULT_PROFILE_NAME.to_string(),
+    }
+}
+
+/// The token from `MOMENTO_API_KEY`, or else `MOMENTO_AUTH_TOKEN`.
+fn get_env_token(env: impl Fn(&str) -> Option<String>) -> Option<Credentials> {
+    [
+        ENV_VAR_NAME_MOMENTO_API_KEY,
+        ENV_VAR_NAME_MOMENTO_AUTH_TOKEN,
+    ]
+    .iter()
+    .find_map(|name| {
+        env(name).map(|token| {
+            log::debug!("Using the token from {name}");
+            Credentials {
+                token: token.into(),
+            }
+        })
+    })
 }
 
+/// Resolve the credentials and settings for a command. Each value comes from the first of
+/// these that provides it: a command line flag (applied by the caller), the `MOMENTO_*`
+/// environment variables, a login session (for the token only), and finally the profile's
+/// entries in the credentials and config files.
 pub async fn get_creds_and_config(profile: &str) -> Result<(Credentials, Config), CliError> {
-    let creds = get_creds_for_profile(profile).await?;
-    let config = get_config_for_profile(profile).await?;
+    let creds = match get_env_token(env_override) {
+        Some(creds) => creds,
+        None => get_creds_for_profile(profile).await?,
+    };
+    let config = get_config_with_env_overrides(profile).await?;
+    init_client_settings(&config);
 
     Ok((creds, config))
 }
 
+async fn get_config_with_env_overrides(profile: &str) -> Result<Config, CliError> {
+    let profile_config = get_config_for_profile(profile).await;
+    apply_env_overrides(profile, profile_config, env_override)
+}
+
+/// Override the settings of `profile_config` with those in the environment, looked up
+/// with `env`.
+fn apply_env_overrides(
+    profile: &str,
+    profile_config: Result<Config, CliError>,
+    env: impl Fn(&str) -> Option<String>,
+) -> Result<Config, CliError> {
+    let env_cache = env(ENV_VAR_NAME_MOMENTO_CACHE);
+    let env_ttl = match env(ENV_VAR_NAME_MOMENTO_TTL) {
+        Some(ttl) => Some(ttl.parse::<u64>().map_err(|e| CliError {
+            msg: format!("could not parse {ENV_VAR_NAME_MOMENTO_TTL} as a u64: {e:?}"),
+        })?),
+        None => None,
+    };
+    let env_endpoint = env(ENV_VAR_NAME_MOMENTO_ENDPOINT);
+
+    // With both the cache and the TTL in the environment, a config file is not required.
+    let profile_config = if env_cache.is_some() && env_ttl.is_some() {
+        profile_config.unwrap_or_default()
+    } else {
+        profile_config?
+    };
+
+    let cache = match env_cache {
+        Some(cache) => {
+            log::debug!("Using cache {cache} from {ENV_VAR_NAME_MOMENTO_CACHE}");
+            cache
+        }
+        None => {
+            log::debug!(
+                "Using cache {} from profile {profile}",
+                profile_config.cache
+            );
+            profile_config.cache
+        }
+    };
+    let ttl = match env_ttl {
+        Some(ttl) => {
+            log::debug!("Using ttl {ttl} from {ENV_VAR_NAME_MOMENTO_TTL}");
+            ttl
+        }
+        None => {
+            log::debug!("Using ttl {} from profile {profile}", profile_config.ttl);
+            profile_config.ttl
+        }
+    };
+    let endpoint = match env_endpoint {
+        Some(endpoint) => {
+            log::debug!("Using endpoint {endpoint} from {ENV_VAR_NAME_MOMENTO_ENDPOINT}");
+            Some(endpoint)
+        }
+        None => {
+            if let Some(endpoint) = &profile_config.endpoint {
+                log::debug!("Using endpoint {endpoint} from profile {profile}");
+            }
+            profile_config.endpoint
+        }
+    };
+    Ok(Config {
+        cache,
+        ttl,
+        endpoint,
+        ..profile_config
+    })
+}
+
 pub async fn get_creds_for_profile(profile: &str) -> Result<Credentials, CliError> {
-    let credentials_file = read_credentials().await?;
+    if let Some(token) = get_session_token(profile).await {
+        return Ok(Credentials { token });
+    }
+    if let Some(settings) = get_toml_profile_settings(profile).await? {
+        return get_creds_from_toml_profile(profile, settings).await;
+    }
+    let credentials_document =
+        IniDocument::parse(&read_protected_lines(&get_credentials_file_path()?).await?);
+    if let Some(token) = credentials_document
+        .get(profile, "token")
+        .filter(|token| !token.is_empty())
+    {
+        log::debug!("Using the token from profile {profile}");
+        return Ok(Credentials {
+            token: token.into(),
+        });
+    }
+    if let Some(command) = credentials_document.get(profile, "credential_process") {
+        let token = get_credential_process_token(profile, command).await?;
+        return Ok(Credentials { token });
+    }
+    Err(CliError {
+        msg: format!("failed to get credentials for profile {profile}, please run 'momento configure' to configure your profile")
+    })
+}
 
-    get_session_token(&credentials_file).or_else(|| {
-        credentials_file.get(profile, "token")
-    }).map(|credentials| {
-        Ok(Credentials {
-            token: credentials,
-        })
-    }).unwrap_or_else(|| {
-        Err(CliError{
-            msg: format!("failed to get credentials for profile {profile}, please run 'momento configure' to configure your profile")
-        })
+async fn get_creds_from_toml_profile(
+    profile: &str,
+    settings: ProfileSettings,
+) -> Result<Credentials, CliError> {
+    if let Some(token) = settings.token.filter(|token| !token.is_empty()) {
+        log::debug!("Using the token from profile {profile} in config.toml");
+        return Ok(Credentials { token });
+    }
+    if let Some(command) = settings.credential_process {
+        let token = get_credential_process_token(profile, &command).await?;
+        return Ok(Credentials { token });
+    }
+    Err(CliError {
+        msg: format!("failed to get credentials for profile {profile}, please run 'momento configure' to configure your profile")
     })
 }
 
-async fn read_credentials() -> Result<Ini, CliError> {
-    let path = get_credentials_file_path()?;
-    read_ini_file(&path).await
+/// The effective settings of `profile`, merged along its `inherits` chain and the defaults
+/// section, each with the section it came from.
+pub async fn get_profile_settings(
+    profile: &str,
+) -> Result<BTreeMap<String, ResolvedSetting>, CliError> {
+    resolve_profile_settings(profile, None).await
 }
 
-pub async fn get_config_for_profile(profile: &str) -> Result<Config, CliError> {
+/// The effective settings `profile` would have if it did not set `key` itself.
+pub async fn get_inherited_profile_settings(
+    profile: &str,
+    key: &str,
+) -> Result<BTreeMap<String, ResolvedSetting>, CliError> {
+    resolve_profile_settings(profile, Some(key)).await
+}
+
+async fn resolve_profile_settings(
+    profile: &str,
+    ignored_key: Option<&str>,
+) -> Result<BTreeMap<String, ResolvedSetting>, CliError> {
+    let ignored = |name: &str, key: &str| name == profile && ignored_key == Some(key);
+    if let Some(toml_config) = read_toml_config()
+        .await?
+        .filter(|toml_config| toml_config.profiles.contains_key(profile))
+    {
+        let chain = inheritance_chain(
+            profile,
+            |name| toml_config.section(name).is_some(),
+            |name| toml_config.section(name).and_then(|s| s.inherits.clone()),
+        )?;
+        return Ok(resolve_settings(&chain, |name, key| {
+            toml_config
+                .section(name)
+                .filter(|_| !ignored(name, key))
+                .and_then(|s| s.get_setting(key))
+        }));
+    }
     let path = get_config_file_path()?;
     let configs = match read_ini_file(&path).await {
         Ok(c) => c,
@@ -100,25 +427,152 @@ pub async fn get_config_for_profile(profile: &str) -> Result<Config, CliError> {
             msg: format!("failed to read credentials, please run 'momento configure' to setup credentials. Root cause: {e:?}")
         }),
     };
+    let in_config = |name: &str| configs.has_section(name);
+    // A profile with only a token still exists, and takes its settings from the defaults.
+    let in_credentials = !in_config(profile)
+        && IniDocument::parse(&read_protected_lines(&get_credentials_file_path()?).await?)
+            .has_section(profile);
+    let chain = inheritance_chain(
+        profile,
+        |name| in_config(name) || (name == profile && in_credentials),
+        |name| {
+            configs
+                .get(name, INHERITS_KEY)
+                .map(|parent| parent.to_string())
+        },
+    )?;
+    Ok(resolve_settings(&chain, |name, key| {
+        configs
+            .get(name, key)
+            .filter(|_| !ignored(name, key))
+            .map(|value| value.to_string())
+    }))
+}
 
-    let cache_result = match configs.get(profile, "cache") {
-        Some(c) => c,
-        None => return Err(CliError{
-            msg: format!("failed to get cache config for profile {profile}, please run 'momento configure' to configure your profile")
-        }),
-    };
+pub async fn get_config_for_profile(profile: &str) -> Result<Config, CliError> {
+    config_from_settings(profile, &get_profile_settings(profile).await?)
+}
 
-    let ttl_result = match configs.get(profile, "ttl") {
-        Some(c) => c,
-        None => return Err(CliError{
-            msg: format!("failed to get ttl config for profile {profile}, please run 'momento configure' to configure your profile")
-        }),
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::config::{
+        ENV_VAR_NAME_MOMENTO_API_KEY, ENV_VAR_NAME_MOMENTO_AUTH_TOKEN, ENV_VAR_NAME_MOMENTO_CACHE,
+        ENV_VAR_NAME_MOMENTO_TTL,
     };
+    use crate::error::CliError;
+    use crate::utils::ini_config::IniDocument;
+    use crate::utils::user::{apply_env_overrides, get_env_token, read_session, set_session_token};
 
-    Ok(Config {
-        cache: cache_result,
-        ttl: ttl_result.parse::<u64>().map_err(|e| CliError {
-            msg: format!("could not parse a u64: {e:?}"),
-        })?,
-    })
+    fn env_of(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .filter(|(_, value)| !value.is_empty())
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    #[test]
+    fn api_key_takes_precedence_over_auth_token() {
+        let token = |vars: &[(&str, &str)]| {
+            get_env_token(env_of(vars))
+                .expect("d'oh")
+                .token
+                .expose_secret()
+                .to_string()
+        };
+        assert_eq!(
+            "auth-token",
+            token(&[(ENV_VAR_NAME_MOMENTO_AUTH_TOKEN, "auth-token")])
+        );
+        assert_eq!(
+            "api-key",
+            token(&[
+                (ENV_VAR_NAME_MOMENTO_AUTH_TOKEN, "auth-token"),
+                (ENV_VAR_NAME_MOMENTO_API_KEY, "api-key"),
+            ])
+        );
+        assert_eq!(
+            "auth-token",
+            token(&[
+                (ENV_VAR_NAME_MOMENTO_AUTH_TOKEN, "auth-token"),
+                (ENV_VAR_NAME_MOMENTO_API_KEY, ""),
+            ])
+        );
+        assert!(get_env_token(env_of(&[])).is_none());
+    }
+
+    #[test]
+    fn environment_settings_do_not_need_a_config_file() {
+        let no_config_file = || {
+            Err(CliError {
+                msg: "failed to read credentials".to_string(),
+            })
+        };
+        let config = apply_env_overrides(
+            "default",
+            no_config_file(),
+            env_of(&[
+                (ENV_VAR_NAME_MOMENTO_CACHE, "env-cache"),
+                (ENV_VAR_NAME_MOMENTO_TTL, "42"),
+            ]),
+        )
+        .expect("d'oh");
+        assert_eq!("env-cache", config.cache);
+        assert_eq!(42, config.ttl);
+
+        assert!(apply_env_overrides(
+            "default",
+            no_config_file(),
+            env_of(&[
+                (ENV_VAR_NAME_MOMENTO_CACHE, "env-cache"),
+                (ENV_VAR_NAME_MOMENTO_TTL, "forever"),
+            ]),
+        )
+        .is_err());
+        assert!(apply_env_overrides(
+            "default",
+            no_config_file(),
+            env_of(&[(ENV_VAR_NAME_MOMENTO_CACHE, "env-cache")]),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn sessions_are_scoped_to_their_profile() {
+        let mut credentials = IniDocument::parse(&[
+            "[default]",
+            "token=default-token",
+            "",
+            "[.momento_session]",
+            "token=legacy-session-token",
+            "valid_until=9999999999",
+        ]);
+        assert!(read_session(&credentials, "default").is_none());
+
+        set_session_token(
+            &mut credentials,
+            "prod",
+            Some("prod-session-token".into()),
+            3600,
+        );
+        let session = read_session(&credentials, "prod").expect("d'oh");
+        assert_eq!("prod-session-token", session.token.expose_secret());
+        assert!(session.is_valid());
+        assert!(read_session(&credentials, "default").is_none());
+        assert_eq!(Some("default-token"), credentials.get("default", "token"));
+    }
+
+    #[test]
+    fn expired_sessions_are_not_valid() {
+        let credentials = IniDocument::parse(&[
+            "[.momento_session.prod]",
+            "token=prod-session-token",
+            "valid_until=1700000000",
+        ]);
+        let session = read_session(&credentials, "prod").expect("d'oh");
+        assert!(!session.is_valid());
+    }
 }